@@ -0,0 +1,302 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module implements one-off commands that operators can run to inspect and debug a mosaic
+//! node without running it.
+
+use futures::Future;
+use rlp;
+use rustc_hex::{FromHex, ToHex};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+use tokio_core::reactor::Core;
+use web3::contract::Options;
+use web3::types::{Address, BlockId, BlockNumber, H256, U128, U256};
+
+use config::{Chain, ReportingPolicy};
+use ethereum::contract::{ContractRegistry, ContractType};
+use ethereum::filter::EventFilter;
+use ethereum::transaction::TransactionOutcome;
+use ethereum::types::Block;
+use reactor::block_reporter::BlockReporter;
+use state::{Checkpoint, StateStore};
+use Config;
+
+/// The state of a block store contract on auxiliary.
+pub struct BlockStoreStatus {
+    /// The chain whose blocks are stored in the block store.
+    pub chain: Chain,
+    /// The address of the block store contract.
+    pub address: Address,
+    /// The hash of the head of the finalised checkpoints.
+    pub head: H256,
+    /// The height of the latest reported block.
+    pub latest_block_height: U256,
+    /// The highest justified checkpoint, as the node last observed it.
+    pub justified_checkpoint: Option<Checkpoint>,
+    /// The highest finalised checkpoint, as the node last observed it.
+    pub finalised_checkpoint: Option<Checkpoint>,
+}
+
+impl fmt::Display for BlockStoreStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} block store {:x}", self.chain, self.address)?;
+        writeln!(f, "  head:                {:x}", self.head)?;
+        writeln!(f, "  latest block height: {}", self.latest_block_height)?;
+        writeln!(
+            f,
+            "  justified:           {}",
+            checkpoint_to_string(self.justified_checkpoint)
+        )?;
+        write!(
+            f,
+            "  finalised:           {}",
+            checkpoint_to_string(self.finalised_checkpoint)
+        )
+    }
+}
+
+/// Formats an optional checkpoint for display.
+fn checkpoint_to_string(checkpoint: Option<Checkpoint>) -> String {
+    match checkpoint {
+        Some(checkpoint) => format!("{:x} at height {}", checkpoint.hash, checkpoint.height),
+        None => "none".to_string(),
+    }
+}
+
+/// The encoding and the hash of a block as they are used when reporting the block.
+pub struct BlockHash {
+    /// The number of the block.
+    pub number: U128,
+    /// The RLP encoded header of the block.
+    pub rlp: Vec<u8>,
+    /// The hash as computed from the RLP encoded header.
+    pub computed_hash: H256,
+    /// The hash as returned by the node.
+    pub node_hash: H256,
+}
+
+impl fmt::Display for BlockHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "block:         {}", self.number)?;
+        writeln!(f, "rlp:           0x{}", self.rlp.to_hex())?;
+        writeln!(f, "computed hash: {:x}", self.computed_hash)?;
+        write!(f, "node hash:     {:x}", self.node_hash)
+    }
+}
+
+/// A block header that was decoded from its RLP encoding.
+pub struct DecodedHeader(Block);
+
+impl fmt::Display for DecodedHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let block = &self.0;
+        writeln!(f, "hash:              {:x}", block.hash)?;
+        writeln!(f, "parent hash:       {:x}", block.parent_hash)?;
+        writeln!(f, "uncles hash:       {:x}", block.uncles_hash)?;
+        writeln!(f, "author:            {:x}", block.author)?;
+        writeln!(f, "state root:        {:x}", block.state_root)?;
+        writeln!(f, "transactions root: {:x}", block.transactions_root)?;
+        writeln!(f, "receipts root:     {:x}", block.receipts_root)?;
+        writeln!(f, "logs bloom:        {:x}", block.logs_bloom)?;
+        writeln!(f, "difficulty:        {}", block.difficulty)?;
+        writeln!(f, "number:            {}", block.number)?;
+        writeln!(f, "gas limit:         {}", block.gas_limit)?;
+        writeln!(f, "gas used:          {}", block.gas_used)?;
+        writeln!(f, "timestamp:         {}", block.timestamp)?;
+        writeln!(f, "extra data:        0x{}", block.extra_data.0.to_hex())?;
+        writeln!(f, "mix hash:          {:x}", block.mix_hash)?;
+        write!(f, "nonce:             {:x}", block.nonce)
+    }
+}
+
+/// Checks the parts of the configuration that the configuration cannot check while it is read,
+/// i.e. that the keystore files of the validators exist and are readable.
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the mosaic node.
+///
+/// # Returns
+///
+/// Returns an error that names every keystore file that cannot be read.
+pub fn check_config(config: &Config) -> Result<(), Box<Error>> {
+    let problems: Vec<String> = [
+        (Chain::Origin, config.origin_keystore_path()),
+        (Chain::Auxiliary, config.auxiliary_keystore_path()),
+    ].iter()
+    .filter_map(|(chain, path)| {
+        fs::read(path).err().map(|error| {
+            format!(
+                "Cannot read the {} keystore file {}: {}",
+                chain,
+                path.display(),
+                error
+            )
+        })
+    }).collect();
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(From::from(problems.join("\n")))
+    }
+}
+
+/// Reads the heads and the latest reported block heights of both block stores, and the latest
+/// justified and finalised checkpoints that the node observed.
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the mosaic node.
+///
+/// # Returns
+///
+/// Returns the status of the origin and the auxiliary block store.
+pub fn status(config: &Config) -> Result<Vec<BlockStoreStatus>, Box<Error>> {
+    let mut event_loop = Core::new()?;
+    let (origin, auxiliary) = ::connect(config, &event_loop.handle())?;
+    let contract_registry = ContractRegistry::new(Arc::new(origin), Arc::new(auxiliary), config)?;
+    let state = StateStore::open(config.state_path())?;
+
+    let mut statuses = Vec::new();
+    for chain in &[Chain::Origin, Chain::Auxiliary] {
+        let block_store = contract_registry.get(&block_store_type(*chain))?;
+        let contract = block_store.contract();
+
+        let head = contract.query("getHead", (), None, Options::default(), None);
+        let latest_block_height =
+            contract.query("latestBlockHeight", (), None, Options::default(), None);
+        let (head, latest_block_height) = event_loop.run(head.join(latest_block_height))?;
+
+        statuses.push(BlockStoreStatus {
+            chain: *chain,
+            address: block_store.address(),
+            head,
+            latest_block_height,
+            justified_checkpoint: state.chain(*chain).justified_checkpoint,
+            finalised_checkpoint: state.chain(*chain).finalised_checkpoint,
+        });
+    }
+
+    Ok(statuses)
+}
+
+/// Reports a single block of the given chain on its block store, the same way that the block
+/// reporter of a running node does. Reads the password of the auxiliary validator from `stdin`.
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the mosaic node.
+/// * `chain` - The chain of the block to report.
+/// * `number` - The number of the block to report.
+///
+/// # Returns
+///
+/// Returns the outcome of the mined report transaction or `None` if the block was already
+/// reported.
+pub fn report_block(
+    config: &Config,
+    chain: Chain,
+    number: u64,
+) -> Result<Option<TransactionOutcome>, Box<Error>> {
+    let mut event_loop = Core::new()?;
+    let (origin, mut auxiliary) = ::connect(config, &event_loop.handle())?;
+    // All block stores are on auxiliary.
+    auxiliary.load_signer(config.auxiliary_keystore_path())?;
+
+    let origin = Arc::new(origin);
+    let auxiliary = Arc::new(auxiliary);
+    let contract_registry =
+        ContractRegistry::new(Arc::clone(&origin), Arc::clone(&auxiliary), config)?;
+
+    let block_chain = match chain {
+        Chain::Origin => origin,
+        Chain::Auxiliary => Arc::clone(&auxiliary),
+    };
+    let block = event_loop.run(block_chain.block(block_id(number), &EventFilter::default()))?;
+
+    // The block was requested explicitly, so it is reported regardless of the reporting policy.
+    let block_reporter = BlockReporter::new(
+        contract_registry.get(&block_store_type(chain))?,
+        config.auxiliary_validator_address(),
+        block_chain,
+        auxiliary,
+        ReportingPolicy::All,
+    );
+
+    Ok(event_loop.run(block_reporter.report(&block))?)
+}
+
+/// Retrieves a block of the given chain and computes its RLP encoding and hash.
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the mosaic node.
+/// * `chain` - The chain of the block.
+/// * `number` - The number of the block.
+///
+/// # Returns
+///
+/// Returns the RLP encoding and the hashes of the block.
+pub fn hash_block(config: &Config, chain: Chain, number: u64) -> Result<BlockHash, Box<Error>> {
+    let mut event_loop = Core::new()?;
+    let (origin, auxiliary) = ::connect(config, &event_loop.handle())?;
+
+    let block_chain = match chain {
+        Chain::Origin => origin,
+        Chain::Auxiliary => auxiliary,
+    };
+    let block = event_loop.run(block_chain.block(block_id(number), &EventFilter::default()))?;
+
+    Ok(BlockHash {
+        number: block.number,
+        rlp: rlp::encode(&block),
+        computed_hash: block.hash(),
+        node_hash: block.hash,
+    })
+}
+
+/// Decodes an RLP encoded block header, e.g. as it is reported to a block store.
+///
+/// # Arguments
+///
+/// * `rlp` - The hex encoded RLP of the header, with or without a leading `0x`.
+///
+/// # Returns
+///
+/// Returns the fields of the header and its hash.
+pub fn decode_header(rlp: &str) -> Result<DecodedHeader, Box<Error>> {
+    let rlp = rlp.trim();
+    let rlp = if rlp.starts_with("0x") { &rlp[2..] } else { rlp };
+    let bytes: Vec<u8> = rlp.from_hex()?;
+    let block = rlp::decode(&bytes).map_err(|error| format!("Invalid block header: {}", error))?;
+
+    Ok(DecodedHeader(block))
+}
+
+/// Returns the type of the block store that stores the blocks of the given chain.
+fn block_store_type(chain: Chain) -> ContractType {
+    match chain {
+        Chain::Origin => ContractType::OriginBlockStore,
+        Chain::Auxiliary => ContractType::AuxiliaryBlockStore,
+    }
+}
+
+/// Returns the id of the block with the given number.
+fn block_id(number: u64) -> BlockId {
+    BlockId::Number(BlockNumber::Number(number))
+}
@@ -0,0 +1,149 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module implements the errors that can appear while reading the configuration.
+
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// A ConfigError describes a single problem with the configuration.
+/// Values are identified by their environment variable and their key in the configuration file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// A mandatory value is set neither in the environment nor in the configuration file.
+    MissingValue { variable: String, file_key: String },
+    /// A value cannot be parsed as an address.
+    InvalidAddress {
+        variable: String,
+        file_key: String,
+        value: String,
+    },
+    /// A value cannot be parsed as a positive duration in seconds.
+    InvalidDuration {
+        variable: String,
+        file_key: String,
+        value: String,
+    },
+    /// A value cannot be parsed as a non-negative number.
+    InvalidNumber {
+        variable: String,
+        file_key: String,
+        value: String,
+    },
+    /// A value cannot be parsed as a multiplier of at least 1.
+    InvalidMultiplier {
+        variable: String,
+        file_key: String,
+        value: String,
+    },
+    /// A value cannot be parsed as a gas price strategy.
+    InvalidGasPriceStrategy {
+        variable: String,
+        file_key: String,
+        value: String,
+        explanation: String,
+    },
+    /// A value cannot be parsed as a reporting policy.
+    InvalidReportingPolicy {
+        variable: String,
+        file_key: String,
+        value: String,
+    },
+    /// A key of the configuration file is not supported in the section that it is set in.
+    UnsupportedKey { file_key: String },
+    /// The configuration file cannot be read or is not valid.
+    InvalidFile { path: PathBuf, explanation: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::MissingValue { variable, file_key } => write!(
+                f,
+                "No value set for {} (environment variable {})",
+                file_key, variable
+            ),
+            ConfigError::InvalidAddress {
+                variable,
+                file_key,
+                value,
+            } => write!(
+                f,
+                "Value '{}' of {} (environment variable {}) is not a valid address",
+                value, file_key, variable
+            ),
+            ConfigError::InvalidDuration {
+                variable,
+                file_key,
+                value,
+            } => write!(
+                f,
+                "Value '{}' of {} (environment variable {}) is not a positive number of seconds",
+                value, file_key, variable
+            ),
+            ConfigError::InvalidNumber {
+                variable,
+                file_key,
+                value,
+            } => write!(
+                f,
+                "Value '{}' of {} (environment variable {}) is not a valid number",
+                value, file_key, variable
+            ),
+            ConfigError::InvalidMultiplier {
+                variable,
+                file_key,
+                value,
+            } => write!(
+                f,
+                "Value '{}' of {} (environment variable {}) is not a multiplier of at least 1",
+                value, file_key, variable
+            ),
+            ConfigError::InvalidGasPriceStrategy {
+                variable,
+                file_key,
+                value,
+                explanation,
+            } => write!(
+                f,
+                "Value '{}' of {} (environment variable {}) is not a valid gas price strategy: {}",
+                value, file_key, variable, explanation
+            ),
+            ConfigError::InvalidReportingPolicy {
+                variable,
+                file_key,
+                value,
+            } => write!(
+                f,
+                "Value '{}' of {} (environment variable {}) is not a reporting policy. \
+                 Allowed are: all, checkpoints",
+                value, file_key, variable
+            ),
+            ConfigError::UnsupportedKey { file_key } => write!(
+                f,
+                "{} is not supported, it is only read from the [origin] section",
+                file_key
+            ),
+            ConfigError::InvalidFile { path, explanation } => write!(
+                f,
+                "Invalid configuration file {}: {}",
+                path.display(),
+                explanation
+            ),
+        }
+    }
+}
+
+impl Error for ConfigError {}
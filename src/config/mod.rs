@@ -13,47 +13,279 @@
 // limitations under the License.
 
 //! This module handles all configuration of this library.
+//! The configuration can be read from a TOML file with an `[origin]` and an `[auxiliary]` section.
+//! Environment variables override the values of the file.
 
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::Duration;
-use web3::types::Address;
+use toml;
+use web3::types::{Address, U256};
+
+pub use self::error::ConfigError;
+
+mod error;
 
 // Environment variables and their defaults
-const ENV_ORIGIN_ENDPOINT: &str = "MOSAIC_ORIGIN_ENDPOINT";
+// The variables of a chain start with the prefix of the chain, e.g. `MOSAIC_ORIGIN_ENDPOINT`.
+const ENV_ORIGIN_PREFIX: &str = "MOSAIC_ORIGIN";
+const ENV_AUXILIARY_PREFIX: &str = "MOSAIC_AUXILIARY";
+const ENV_ENDPOINT: &str = "ENDPOINT";
 const DEFAULT_ORIGIN_ENDPOINT: &str = "http://127.0.0.1:8545";
-const ENV_AUXILIARY_ENDPOINT: &str = "MOSAIC_AUXILIARY_ENDPOINT";
 const DEFAULT_AUXILIARY_ENDPOINT: &str = "http://127.0.0.1:8546";
 const ENV_ORIGIN_CORE_ADDRESS: &str = "MOSAIC_ORIGIN_CORE_ADDRESS";
-const ENV_ORIGIN_VALIDATOR_ADDRESS: &str = "MOSAIC_ORIGIN_VALIDATOR_ADDRESS";
-const ENV_AUXILIARY_VALIDATOR_ADDRESS: &str = "MOSAIC_AUXILIARY_VALIDATOR_ADDRESS";
-const ENV_ORIGIN_BLOCK_STORE_ADDRESS: &str = "MOSAIC_ORIGIN_BLOCK_STORE_ADDRESS";
-const ENV_AUXILIARY_BLOCK_STORE_ADDRESS: &str = "MOSAIC_AUXILIARY_BLOCK_STORE_ADDRESS";
-const ENV_ORIGIN_POLLING_INTERVAL: &str = "MOSAIC_ORIGIN_POLLING_INTERVAL";
-const DEFAULT_ORIGIN_POLLING_INTERVAL: &str = "1";
-const ENV_AUXILIARY_POLLING_INTERVAL: &str = "MOSAIC_AUXILIARY_POLLING_INTERVAL";
-const DEFAULT_AUXILIARY_POLLING_INTERVAL: &str = "1";
+const ENV_VALIDATOR_ADDRESS: &str = "VALIDATOR_ADDRESS";
+const ENV_BLOCK_STORE_ADDRESS: &str = "BLOCK_STORE_ADDRESS";
+const ENV_KEYSTORE_PATH: &str = "KEYSTORE_PATH";
+const ENV_POLLING_INTERVAL: &str = "POLLING_INTERVAL";
+const DEFAULT_POLLING_INTERVAL: &str = "1";
+const ENV_MAX_RETRIES: &str = "MAX_RETRIES";
+const DEFAULT_MAX_RETRIES: &str = "5";
+const ENV_RETRY_DELAY: &str = "RETRY_DELAY";
+const DEFAULT_RETRY_DELAY: &str = "500";
+const ENV_STATE_PATH: &str = "MOSAIC_STATE_PATH";
+const DEFAULT_STATE_PATH: &str = "mosaic_state.json";
+const ENV_CONFIRMATIONS: &str = "CONFIRMATIONS";
+const DEFAULT_CONFIRMATIONS: &str = "0";
+const ENV_GAS_MULTIPLIER: &str = "GAS_MULTIPLIER";
+const DEFAULT_GAS_MULTIPLIER: &str = "1.2";
+const ENV_GAS_PRICE: &str = "GAS_PRICE";
+const DEFAULT_GAS_PRICE: &str = "node";
+const ENV_MAX_GAS_PRICE: &str = "MAX_GAS_PRICE";
+const ENV_REPORTING_POLICY: &str = "REPORTING_POLICY";
+const DEFAULT_REPORTING_POLICY: &str = "all";
+
+/// The structure of a TOML configuration file.
+/// All values are optional, as they can also be set through the environment or have a default.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    /// The path to the file that stores the state of the node between runs.
+    state_path: Option<String>,
+    #[serde(default)]
+    origin: ChainFileConfig,
+    #[serde(default)]
+    auxiliary: ChainFileConfig,
+}
+
+/// The settings of one chain in a TOML configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainFileConfig {
+    endpoint: Option<String>,
+    /// Multiple endpoints of the same chain, in order of preference. Takes precedence over
+    /// `endpoint`.
+    endpoints: Option<Vec<String>>,
+    validator_address: Option<String>,
+    block_store_address: Option<String>,
+    keystore_path: Option<String>,
+    /// The polling interval in seconds.
+    polling_interval: Option<u64>,
+    /// The number of blocks that must follow a block before it is handed to the reactors.
+    confirmations: Option<u64>,
+    /// The maximum number of retries of a failed request to the node. Each retry goes to the
+    /// next endpoint if the chain has several.
+    max_retries: Option<u64>,
+    /// The delay before the first retry in milliseconds.
+    retry_delay: Option<u64>,
+    /// The factor that the estimated gas of a transaction is multiplied with.
+    gas_multiplier: Option<f64>,
+    /// The gas price strategy, e.g. "node", "fixed:20000000000", or "percentile:60".
+    gas_price: Option<String>,
+    /// The maximum gas price in wei.
+    max_gas_price: Option<u64>,
+    /// The blocks of the chain that are reported, "all" or "checkpoints".
+    reporting_policy: Option<String>,
+    /// The core address is only read from the `[origin]` section. It is an error to set it in the
+    /// `[auxiliary]` section.
+    core_address: Option<String>,
+}
+
+impl FileConfig {
+    /// Reads and parses a TOML configuration file.
+    fn from_file(path: &Path) -> Result<FileConfig, Vec<ConfigError>> {
+        let content = fs::read_to_string(path).map_err(|error| {
+            vec![ConfigError::InvalidFile {
+                path: path.to_owned(),
+                explanation: error.to_string(),
+            }]
+        })?;
+
+        toml::from_str(&content).map_err(|error| {
+            vec![ConfigError::InvalidFile {
+                path: path.to_owned(),
+                explanation: error.to_string(),
+            }]
+        })
+    }
+}
+
+impl ChainFileConfig {
+    /// Returns the endpoints of the chain as a comma separated list, like the environment
+    /// variables expect them.
+    fn endpoints(&self) -> Option<String> {
+        match &self.endpoints {
+            Some(endpoints) => Some(endpoints.join(",")),
+            None => self.endpoint.clone(),
+        }
+    }
+}
+
+/// The chains that a mosaic node connects to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chain {
+    Origin,
+    Auxiliary,
+}
+
+impl FromStr for Chain {
+    type Err = String;
+
+    /// Parses a chain from its name, e.g. "origin" or "auxiliary".
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "origin" => Ok(Chain::Origin),
+            "auxiliary" => Ok(Chain::Auxiliary),
+            _ => Err(format!(
+                "Unknown chain '{}'. Allowed are: origin, auxiliary",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chain::Origin => write!(f, "origin"),
+            Chain::Auxiliary => write!(f, "auxiliary"),
+        }
+    }
+}
+
+/// The strategies to determine the gas price of transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasPriceStrategy {
+    /// A fixed gas price in wei.
+    Fixed(U256),
+    /// The gas price that the node suggests with `eth_gasPrice`.
+    Node,
+    /// A percentile, from 0 to 100, of the gas prices of the transactions in recent blocks.
+    Percentile(u64),
+}
+
+impl Default for GasPriceStrategy {
+    fn default() -> Self {
+        GasPriceStrategy::Node
+    }
+}
+
+impl FromStr for GasPriceStrategy {
+    type Err = String;
+
+    /// Parses a strategy from "node", "fixed:<wei>", or "percentile:<0 to 100>".
+    fn from_str(strategy: &str) -> Result<Self, Self::Err> {
+        let mut parts = strategy.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("node"), None) => Ok(GasPriceStrategy::Node),
+            (Some("fixed"), Some(wei)) => wei
+                .parse::<u64>()
+                .map(|wei| GasPriceStrategy::Fixed(wei.into()))
+                .map_err(|_| format!("Invalid gas price '{}'", wei)),
+            (Some("percentile"), Some(percentile)) => match percentile.parse::<u64>() {
+                Ok(percentile) if percentile <= 100 => {
+                    Ok(GasPriceStrategy::Percentile(percentile))
+                }
+                _ => Err(format!("Invalid percentile '{}'", percentile)),
+            },
+            _ => Err(format!(
+                "Unknown gas price strategy '{}'. \
+                 Allowed are: node, fixed:<wei>, percentile:<0-100>",
+                strategy
+            )),
+        }
+    }
+}
+
+/// The policies that define which blocks of a chain are reported on its block store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportingPolicy {
+    /// Every block is reported.
+    All,
+    /// Only checkpoints, i.e. blocks at epoch boundaries, and explicitly requested blocks are
+    /// reported, each together with its unreported ancestors.
+    Checkpoints,
+}
+
+impl Default for ReportingPolicy {
+    fn default() -> Self {
+        ReportingPolicy::All
+    }
+}
+
+impl FromStr for ReportingPolicy {
+    type Err = String;
+
+    /// Parses a policy from "all" or "checkpoints".
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "all" => Ok(ReportingPolicy::All),
+            "checkpoints" => Ok(ReportingPolicy::Checkpoints),
+            _ => Err(format!(
+                "Unknown reporting policy '{}'. Allowed are: all, checkpoints",
+                policy
+            )),
+        }
+    }
+}
 
 /// Global config for running a mosaic node.
 #[derive(Default)]
 pub struct Config {
-    /// Address of the origin chain, e.g. "127.0.0.1:8485"
-    origin_endpoint: String,
-    /// Address of the auxiliary chain, e.g. "127.0.0.1:8486"
-    auxiliary_endpoint: String,
+    /// The settings of the origin chain.
+    origin: ChainConfig,
+    /// The settings of the auxiliary chain.
+    auxiliary: ChainConfig,
     /// The address of a core address on origin.
     /// It is optional as it may not be needed depending on the mode that the node is run in.
     _origin_core_address: Option<Address>,
-    /// The address that is used to send messages as a validator on origin.
-    origin_validator_address: Address,
-    /// The address that is used to send messages as a validator on auxiliary.
-    auxiliary_validator_address: Address,
-    /// The address of origin block store contract.
-    origin_block_store_address: Address,
-    /// The address of auxiliary block store contract.
-    auxiliary_block_store_address: Address,
-    origin_polling_interval: Duration,
-    auxiliary_polling_interval: Duration,
+    /// The path to the file that stores the state of the node between runs.
+    state_path: PathBuf,
+}
+
+/// The settings of one chain.
+#[derive(Default)]
+struct ChainConfig {
+    /// Addresses of the chain, e.g. "http://127.0.0.1:8485", "ws://127.0.0.1:8486", or the path to
+    /// an IPC socket, e.g. "/var/geth/geth.ipc". The first one is preferred, the others are
+    /// fallbacks.
+    endpoints: Vec<String>,
+    /// The address that is used to send messages as a validator on the chain.
+    validator_address: Address,
+    /// The address of the block store contract of the chain.
+    block_store_address: Address,
+    /// The path to the encrypted keystore file of the validator of the chain.
+    keystore_path: PathBuf,
+    polling_interval: Duration,
+    /// The number of blocks that must follow a block before the reactors react on it.
+    confirmations: u64,
+    /// The maximum number of retries of a failed request to the node.
+    max_retries: u64,
+    /// The delay before the first retry of a failed request to the node.
+    retry_delay: Duration,
+    /// The factor that the estimated gas of a transaction is multiplied with.
+    gas_multiplier: f64,
+    /// The strategy to determine the gas price of transactions.
+    gas_price_strategy: GasPriceStrategy,
+    /// The maximum gas price of transactions in wei.
+    max_gas_price: Option<U256>,
+    /// The policy that defines which blocks are reported.
+    reporting_policy: ReportingPolicy,
 }
 
 impl Config {
@@ -63,132 +295,427 @@ impl Config {
     /// # Returns
     ///
     /// Returns a configuration with the settings read from the environment.
+    /// Returns all problems found if a mandatory value is not set and there is no default or if a
+    /// value cannot be parsed into its appropriate type.
+    pub fn new() -> Result<Config, Vec<ConfigError>> {
+        Self::from_sources(&FileConfig::default(), environment())
+    }
+
+    /// Reads the configuration from a TOML file and creates a new Config from it. Environment
+    /// variables override the values from the file. In case a value is neither set in the file nor
+    /// in the environment, a default fallback will be used if available.
     ///
-    /// # Panics
+    /// # Arguments
+    ///
+    /// * `path` - The path to the TOML configuration file.
+    ///
+    /// # Returns
     ///
-    /// This function panics if a mandatory value cannot be read and there is no default.
-    /// This function also panics if a value cannot be parsed into its appropriate type.
-    pub fn new() -> Config {
-        let origin_endpoint = match Self::read_environment_variable(
-            ENV_ORIGIN_ENDPOINT,
-            Some(DEFAULT_ORIGIN_ENDPOINT),
+    /// Returns a configuration with the settings read from the file and the environment.
+    /// Returns an error if the file cannot be read or is not valid TOML. Otherwise returns all
+    /// problems that `Config::new()` would return.
+    pub fn from_file(path: &Path) -> Result<Config, Vec<ConfigError>> {
+        let file_config = FileConfig::from_file(path)?;
+
+        Self::from_sources(&file_config, environment())
+    }
+
+    /// Creates a new Config from the values of the environment, a configuration file, and the
+    /// defaults, in descending order of precedence.
+    /// Reads all values before returning, so that all problems are reported at once.
+    fn from_sources(
+        file_config: &FileConfig,
+        environment: HashMap<String, String>,
+    ) -> Result<Config, Vec<ConfigError>> {
+        let mut reader = Reader::new(environment);
+        let mut config = Config::default();
+
+        config.origin = Self::read_chain(
+            &mut reader,
+            &file_config.origin,
+            ENV_ORIGIN_PREFIX,
+            Chain::Origin,
+        );
+        config.auxiliary = Self::read_chain(
+            &mut reader,
+            &file_config.auxiliary,
+            ENV_AUXILIARY_PREFIX,
+            Chain::Auxiliary,
+        );
+
+        if let Some(origin_core_address) = reader.read(
+            ENV_ORIGIN_CORE_ADDRESS,
+            file_config.origin.core_address.clone(),
+            None,
         ) {
-            Some(origin_endpoint) => origin_endpoint,
-            None => panic!("An origin endpoint must be set"),
-        };
-        let auxiliary_endpoint = match Self::read_environment_variable(
-            ENV_AUXILIARY_ENDPOINT,
-            Some(DEFAULT_AUXILIARY_ENDPOINT),
+            config._origin_core_address = reader.address(
+                ENV_ORIGIN_CORE_ADDRESS,
+                "origin.core_address",
+                origin_core_address,
+            );
+        }
+        if file_config.auxiliary.core_address.is_some() {
+            reader.errors.push(ConfigError::UnsupportedKey {
+                file_key: "auxiliary.core_address".to_owned(),
+            });
+        }
+
+        if let Some(state_path) = reader.mandatory(
+            ENV_STATE_PATH,
+            "state_path",
+            file_config.state_path.clone(),
+            Some(DEFAULT_STATE_PATH),
         ) {
-            Some(auxiliary_endpoint) => auxiliary_endpoint,
-            None => panic!("An auxiliary endpoint must be set"),
+            config.state_path = PathBuf::from(state_path);
+        }
+
+        if reader.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(reader.errors)
+        }
+    }
+
+    /// Reads the settings of one chain from the environment, its section of the configuration
+    /// file, and the defaults.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader that collects all problems.
+    /// * `section` - The section of the chain in the configuration file.
+    /// * `prefix` - The prefix of the environment variables of the chain, e.g. "MOSAIC_ORIGIN".
+    /// * `chain` - The chain, whose name is the name of its section in the configuration file.
+    ///
+    /// # Returns
+    ///
+    /// Returns the settings of the chain. Settings that have a problem keep their default value.
+    fn read_chain(
+        reader: &mut Reader,
+        section: &ChainFileConfig,
+        prefix: &str,
+        chain: Chain,
+    ) -> ChainConfig {
+        let mut config = ChainConfig::default();
+        let variable = |name: &str| format!("{}_{}", prefix, name);
+        let file_key = |key: &str| format!("{}.{}", chain, key);
+        let default_endpoint = match chain {
+            Chain::Origin => DEFAULT_ORIGIN_ENDPOINT,
+            Chain::Auxiliary => DEFAULT_AUXILIARY_ENDPOINT,
         };
 
-        let origin_core_address =
-            match Self::read_environment_variable(ENV_ORIGIN_CORE_ADDRESS, None) {
-                Some(origin_core_address) => Some(
-                    origin_core_address
-                        .parse::<Address>()
-                        .expect("The origin core address cannot be parsed"),
-                ),
-                None => None,
-            };
-
-        let origin_validator_address =
-            match Self::read_environment_variable(ENV_ORIGIN_VALIDATOR_ADDRESS, None) {
-                Some(origin_validator_address) => origin_validator_address
-                    .parse::<Address>()
-                    .expect("The origin validator address cannot be parsed"),
-                None => panic!("An origin validator address must be set"),
-            };
+        let (name, key) = (variable(ENV_ENDPOINT), file_key("endpoint"));
+        if let Some(endpoints) = reader
+            .mandatory(&name, &key, section.endpoints(), Some(default_endpoint))
+            .and_then(|value| reader.endpoints(&name, &key, &value))
+        {
+            config.endpoints = endpoints;
+        }
 
-        let auxiliary_validator_address =
-            match Self::read_environment_variable(ENV_AUXILIARY_VALIDATOR_ADDRESS, None) {
-                Some(auxiliary_validator_address) => auxiliary_validator_address
-                    .parse::<Address>()
-                    .expect("The auxiliary validator address cannot be parsed"),
-                None => panic!("An auxiliary validator address must be set"),
-            };
+        let (name, key) = (
+            variable(ENV_VALIDATOR_ADDRESS),
+            file_key("validator_address"),
+        );
+        if let Some(validator_address) = reader
+            .mandatory(&name, &key, section.validator_address.clone(), None)
+            .and_then(|value| reader.address(&name, &key, value))
+        {
+            config.validator_address = validator_address;
+        }
 
-        let origin_block_store_address =
-            match Self::read_environment_variable(ENV_ORIGIN_BLOCK_STORE_ADDRESS, None) {
-                Some(auxiliary_validator_address) => auxiliary_validator_address
-                    .parse::<Address>()
-                    .expect("The origin block store address cannot be parsed"),
-                None => panic!("An origin block store address must be set"),
-            };
+        let (name, key) = (
+            variable(ENV_BLOCK_STORE_ADDRESS),
+            file_key("block_store_address"),
+        );
+        if let Some(block_store_address) = reader
+            .mandatory(&name, &key, section.block_store_address.clone(), None)
+            .and_then(|value| reader.address(&name, &key, value))
+        {
+            config.block_store_address = block_store_address;
+        }
 
-        let auxiliary_block_store_address =
-            match Self::read_environment_variable(ENV_AUXILIARY_BLOCK_STORE_ADDRESS, None) {
-                Some(auxiliary_validator_address) => auxiliary_validator_address
-                    .parse::<Address>()
-                    .expect("The auxiliary block store address cannot be parsed"),
-                None => panic!("An auxiliary block store address must be set"),
-            };
+        let (name, key) = (variable(ENV_KEYSTORE_PATH), file_key("keystore_path"));
+        if let Some(keystore_path) =
+            reader.mandatory(&name, &key, section.keystore_path.clone(), None)
+        {
+            config.keystore_path = PathBuf::from(keystore_path);
+        }
 
-        let origin_polling_interval = match Self::read_environment_variable(
-            ENV_ORIGIN_POLLING_INTERVAL,
-            Some(DEFAULT_ORIGIN_POLLING_INTERVAL),
-        ) {
-            Some(origin_polling_interval) => match string_to_seconds(&origin_polling_interval) {
-                Ok(duration) => duration,
-                Err(error) => panic!(
-                    "Could not parse given seconds '{}' to origin polling interval: {}",
-                    origin_polling_interval, error
-                ),
-            },
-            None => panic!("An origin polling period must be set"),
-        };
+        let (name, key) = (variable(ENV_POLLING_INTERVAL), file_key("polling_interval"));
+        if let Some(polling_interval) = reader
+            .mandatory(
+                &name,
+                &key,
+                section.polling_interval.map(|seconds| seconds.to_string()),
+                Some(DEFAULT_POLLING_INTERVAL),
+            ).and_then(|value| reader.duration(&name, &key, value))
+        {
+            config.polling_interval = polling_interval;
+        }
+
+        let (name, key) = (variable(ENV_CONFIRMATIONS), file_key("confirmations"));
+        if let Some(confirmations) = reader
+            .mandatory(
+                &name,
+                &key,
+                section
+                    .confirmations
+                    .map(|confirmations| confirmations.to_string()),
+                Some(DEFAULT_CONFIRMATIONS),
+            ).and_then(|value| reader.number(&name, &key, value))
+        {
+            config.confirmations = confirmations;
+        }
 
-        let auxiliary_polling_interval = match Self::read_environment_variable(
-            ENV_AUXILIARY_POLLING_INTERVAL,
-            Some(DEFAULT_AUXILIARY_POLLING_INTERVAL),
+        let (name, key) = (variable(ENV_MAX_RETRIES), file_key("max_retries"));
+        if let Some(max_retries) = reader
+            .mandatory(
+                &name,
+                &key,
+                section.max_retries.map(|retries| retries.to_string()),
+                Some(DEFAULT_MAX_RETRIES),
+            ).and_then(|value| reader.number(&name, &key, value))
+        {
+            config.max_retries = max_retries;
+        }
+
+        let (name, key) = (variable(ENV_RETRY_DELAY), file_key("retry_delay"));
+        if let Some(retry_delay) = reader
+            .mandatory(
+                &name,
+                &key,
+                section
+                    .retry_delay
+                    .map(|milliseconds| milliseconds.to_string()),
+                Some(DEFAULT_RETRY_DELAY),
+            ).and_then(|value| reader.number(&name, &key, value))
+        {
+            config.retry_delay = Duration::from_millis(retry_delay);
+        }
+
+        let (name, key) = (variable(ENV_GAS_MULTIPLIER), file_key("gas_multiplier"));
+        if let Some(gas_multiplier) = reader
+            .mandatory(
+                &name,
+                &key,
+                section
+                    .gas_multiplier
+                    .map(|multiplier| multiplier.to_string()),
+                Some(DEFAULT_GAS_MULTIPLIER),
+            ).and_then(|value| reader.multiplier(&name, &key, value))
+        {
+            config.gas_multiplier = gas_multiplier;
+        }
+
+        let (name, key) = (variable(ENV_GAS_PRICE), file_key("gas_price"));
+        if let Some(gas_price_strategy) = reader
+            .mandatory(
+                &name,
+                &key,
+                section.gas_price.clone(),
+                Some(DEFAULT_GAS_PRICE),
+            ).and_then(|value| reader.gas_price_strategy(&name, &key, value))
+        {
+            config.gas_price_strategy = gas_price_strategy;
+        }
+
+        let (name, key) = (variable(ENV_MAX_GAS_PRICE), file_key("max_gas_price"));
+        if let Some(max_gas_price) = reader.read(
+            &name,
+            section.max_gas_price.map(|wei| wei.to_string()),
+            None,
         ) {
-            Some(auxiliary_polling_interval) => {
-                match string_to_seconds(&auxiliary_polling_interval) {
-                    Ok(duration) => duration,
-                    Err(error) => panic!(
-                        "Could not parse given seconds '{}' to origin polling interval: {}",
-                        auxiliary_polling_interval, error
-                    ),
-                }
-            }
-            None => panic!("An auxiliary polling period must be set"),
-        };
+            config.max_gas_price = reader.number(&name, &key, max_gas_price).map(U256::from);
+        }
+
+        let (name, key) = (variable(ENV_REPORTING_POLICY), file_key("reporting_policy"));
+        if let Some(reporting_policy) = reader
+            .mandatory(
+                &name,
+                &key,
+                section.reporting_policy.clone(),
+                Some(DEFAULT_REPORTING_POLICY),
+            ).and_then(|value| reader.reporting_policy(&name, &key, value))
+        {
+            config.reporting_policy = reporting_policy;
+        }
+
+        config
+    }
+
+    /// Returns the origin endpoints set on this config, in order of preference.
+    pub fn origin_endpoints(&self) -> &[String] {
+        &self.origin.endpoints
+    }
+
+    /// Returns the auxiliary endpoints set on this config, in order of preference.
+    pub fn auxiliary_endpoints(&self) -> &[String] {
+        &self.auxiliary.endpoints
+    }
+
+    /// Returns the origin validator address set on this config.
+    pub fn origin_validator_address(&self) -> Address {
+        self.origin.validator_address
+    }
+
+    /// Returns the auxiliary validator address set on this config.
+    pub fn auxiliary_validator_address(&self) -> Address {
+        self.auxiliary.validator_address
+    }
+
+    /// Returns the address of origin block store.
+    pub fn origin_block_store_address(&self) -> Address {
+        self.origin.block_store_address
+    }
 
-        Config {
-            origin_endpoint,
-            auxiliary_endpoint,
-            _origin_core_address: origin_core_address,
-            origin_validator_address,
-            auxiliary_validator_address,
-            origin_block_store_address,
-            auxiliary_block_store_address,
-            origin_polling_interval,
-            auxiliary_polling_interval,
+    /// Returns the address of auxiliary block store.
+    pub fn auxiliary_block_store_address(&self) -> Address {
+        self.auxiliary.block_store_address
+    }
+
+    /// Returns the path to the keystore file of the origin validator.
+    pub fn origin_keystore_path(&self) -> &Path {
+        &self.origin.keystore_path
+    }
+
+    /// Returns the path to the keystore file of the auxiliary validator.
+    pub fn auxiliary_keystore_path(&self) -> &Path {
+        &self.auxiliary.keystore_path
+    }
+
+    pub fn origin_polling_interval(&self) -> Duration {
+        self.origin.polling_interval
+    }
+
+    pub fn auxiliary_polling_interval(&self) -> Duration {
+        self.auxiliary.polling_interval
+    }
+
+    /// Returns the number of blocks that must follow an origin block before it is handed to the
+    /// reactors.
+    pub fn origin_confirmations(&self) -> u64 {
+        self.origin.confirmations
+    }
+
+    /// Returns the number of blocks that must follow an auxiliary block before it is handed to the
+    /// reactors.
+    pub fn auxiliary_confirmations(&self) -> u64 {
+        self.auxiliary.confirmations
+    }
+
+    /// Returns the maximum number of retries of a failed request to the origin node.
+    pub fn origin_max_retries(&self) -> u64 {
+        self.origin.max_retries
+    }
+
+    /// Returns the maximum number of retries of a failed request to the auxiliary node.
+    pub fn auxiliary_max_retries(&self) -> u64 {
+        self.auxiliary.max_retries
+    }
+
+    /// Returns the delay before the first retry of a failed request to the origin node.
+    pub fn origin_retry_delay(&self) -> Duration {
+        self.origin.retry_delay
+    }
+
+    /// Returns the delay before the first retry of a failed request to the auxiliary node.
+    pub fn auxiliary_retry_delay(&self) -> Duration {
+        self.auxiliary.retry_delay
+    }
+
+    /// Returns the factor that the estimated gas of an origin transaction is multiplied with.
+    pub fn origin_gas_multiplier(&self) -> f64 {
+        self.origin.gas_multiplier
+    }
+
+    /// Returns the factor that the estimated gas of an auxiliary transaction is multiplied with.
+    pub fn auxiliary_gas_multiplier(&self) -> f64 {
+        self.auxiliary.gas_multiplier
+    }
+
+    /// Returns the strategy to determine the gas price of origin transactions.
+    pub fn origin_gas_price_strategy(&self) -> GasPriceStrategy {
+        self.origin.gas_price_strategy
+    }
+
+    /// Returns the strategy to determine the gas price of auxiliary transactions.
+    pub fn auxiliary_gas_price_strategy(&self) -> GasPriceStrategy {
+        self.auxiliary.gas_price_strategy
+    }
+
+    /// Returns the maximum gas price of origin transactions in wei, if any.
+    pub fn origin_max_gas_price(&self) -> Option<U256> {
+        self.origin.max_gas_price
+    }
+
+    /// Returns the maximum gas price of auxiliary transactions in wei, if any.
+    pub fn auxiliary_max_gas_price(&self) -> Option<U256> {
+        self.auxiliary.max_gas_price
+    }
+
+    /// Returns the policy that defines which origin blocks are reported.
+    pub fn origin_reporting_policy(&self) -> ReportingPolicy {
+        self.origin.reporting_policy
+    }
+
+    /// Returns the policy that defines which auxiliary blocks are reported.
+    pub fn auxiliary_reporting_policy(&self) -> ReportingPolicy {
+        self.auxiliary.reporting_policy
+    }
+
+    /// Returns the path to the file that stores the state of the node between runs.
+    pub fn state_path(&self) -> &Path {
+        &self.state_path
+    }
+}
+
+/// A reader reads configuration values from the environment, the configuration file, and the
+/// defaults. It collects all problems that it finds instead of stopping at the first one.
+struct Reader {
+    errors: Vec<ConfigError>,
+    /// The environment variables that the reader reads from.
+    environment: HashMap<String, String>,
+}
+
+impl Reader {
+    /// Creates a new reader that reads from the given environment variables.
+    fn new(environment: HashMap<String, String>) -> Self {
+        Reader {
+            errors: vec![],
+            environment,
         }
     }
 
-    /// Reads an environment variable and return the value if found or a default if given.
+    /// Reads an environment variable and return the value if found. Otherwise returns the value
+    /// from the configuration file or a default if given.
     ///
     /// # Arguments
     ///
     /// * `name` - The name of the environment variable.
-    /// * `default_value` - An optional default value if the environment variable is not set.
+    /// * `file_value` - An optional value from the configuration file.
+    /// * `default_value` - An optional default value if neither the environment variable nor the
+    /// file value is set.
     ///
     /// # Returns
     ///
-    /// An optional string that is the value of the environment variable if set or the default if
-    /// given.
-    fn read_environment_variable(name: &str, default_value: Option<&str>) -> Option<String> {
-        let value = match env::var(name) {
-            Ok(value) => Some(value),
-            Err(_) => match default_value {
-                Some(default_value) => {
-                    info!("No {} found, falling back to default.", name);
-                    Some(default_value.to_owned())
-                }
-                None => None,
+    /// An optional string that is the value of the environment variable if set, the file value if
+    /// set, or the default if given.
+    fn read(
+        &self,
+        name: &str,
+        file_value: Option<String>,
+        default_value: Option<&str>,
+    ) -> Option<String> {
+        let value = match self.environment.get(name) {
+            Some(value) => Some(value.clone()),
+            None => match file_value {
+                Some(file_value) => Some(file_value),
+                None => match default_value {
+                    Some(default_value) => {
+                        info!("No {} found, falling back to default.", name);
+                        Some(default_value.to_owned())
+                    }
+                    None => None,
+                },
             },
         };
 
@@ -204,45 +731,161 @@ impl Config {
         value
     }
 
-    /// Returns the origin endpoint set on this config.
-    pub fn origin_endpoint(&self) -> &String {
-        &self.origin_endpoint
+    /// Reads a value like `read()` does, but records an error if the value is not set.
+    fn mandatory(
+        &mut self,
+        name: &str,
+        file_key: &str,
+        file_value: Option<String>,
+        default_value: Option<&str>,
+    ) -> Option<String> {
+        let value = self.read(name, file_value, default_value);
+        if value.is_none() {
+            self.errors.push(ConfigError::MissingValue {
+                variable: name.to_owned(),
+                file_key: file_key.to_owned(),
+            });
+        }
+
+        value
     }
 
-    /// Returns the auxiliary endpoint set on this config.
-    pub fn auxiliary_endpoint(&self) -> &String {
-        &self.auxiliary_endpoint
+    /// Parses a value into an address and records an error if that is not possible.
+    fn address(&mut self, name: &str, file_key: &str, value: String) -> Option<Address> {
+        match value.parse::<Address>() {
+            Ok(address) => Some(address),
+            Err(_) => {
+                self.errors.push(ConfigError::InvalidAddress {
+                    variable: name.to_owned(),
+                    file_key: file_key.to_owned(),
+                    value,
+                });
+                None
+            }
+        }
     }
 
-    /// Returns the origin validator address set on this config.
-    pub fn origin_validator_address(&self) -> Address {
-        self.origin_validator_address
+    /// Splits a comma separated list of endpoints and records an error if the list is empty.
+    fn endpoints(&mut self, name: &str, file_key: &str, value: &str) -> Option<Vec<String>> {
+        let endpoints: Vec<String> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|endpoint| !endpoint.is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        if endpoints.is_empty() {
+            self.errors.push(ConfigError::MissingValue {
+                variable: name.to_owned(),
+                file_key: file_key.to_owned(),
+            });
+            None
+        } else {
+            Some(endpoints)
+        }
     }
 
-    /// Returns the auxiliary validator address set on this config.
-    pub fn auxiliary_validator_address(&self) -> Address {
-        self.auxiliary_validator_address
+    /// Parses a value into a positive duration in seconds and records an error if that is not
+    /// possible.
+    fn duration(&mut self, name: &str, file_key: &str, value: String) -> Option<Duration> {
+        match string_to_seconds(&value) {
+            Ok(duration) if duration > Duration::from_secs(0) => Some(duration),
+            _ => {
+                self.errors.push(ConfigError::InvalidDuration {
+                    variable: name.to_owned(),
+                    file_key: file_key.to_owned(),
+                    value,
+                });
+                None
+            }
+        }
     }
 
-    /// Returns the address of origin block store.
-    pub fn origin_block_store_address(&self) -> Address {
-        self.origin_block_store_address
+    /// Parses a value into a multiplier of at least 1 and records an error if that is not
+    /// possible.
+    fn multiplier(&mut self, name: &str, file_key: &str, value: String) -> Option<f64> {
+        match value.parse::<f64>() {
+            Ok(multiplier) if multiplier.is_finite() && multiplier >= 1.0 => Some(multiplier),
+            _ => {
+                self.errors.push(ConfigError::InvalidMultiplier {
+                    variable: name.to_owned(),
+                    file_key: file_key.to_owned(),
+                    value,
+                });
+                None
+            }
+        }
     }
 
-    /// Returns the address of auxiliary block store.
-    pub fn auxiliary_block_store_address(&self) -> Address {
-        self.auxiliary_block_store_address
+    /// Parses a value into a gas price strategy and records an error if that is not possible.
+    fn gas_price_strategy(
+        &mut self,
+        name: &str,
+        file_key: &str,
+        value: String,
+    ) -> Option<GasPriceStrategy> {
+        match value.parse::<GasPriceStrategy>() {
+            Ok(strategy) => Some(strategy),
+            Err(explanation) => {
+                self.errors.push(ConfigError::InvalidGasPriceStrategy {
+                    variable: name.to_owned(),
+                    file_key: file_key.to_owned(),
+                    value,
+                    explanation,
+                });
+                None
+            }
+        }
     }
 
-    pub fn origin_polling_interval(&self) -> Duration {
-        self.origin_polling_interval
+    /// Parses a value into a reporting policy and records an error if that is not possible.
+    fn reporting_policy(
+        &mut self,
+        name: &str,
+        file_key: &str,
+        value: String,
+    ) -> Option<ReportingPolicy> {
+        match value.parse::<ReportingPolicy>() {
+            Ok(policy) => Some(policy),
+            Err(_) => {
+                self.errors.push(ConfigError::InvalidReportingPolicy {
+                    variable: name.to_owned(),
+                    file_key: file_key.to_owned(),
+                    value,
+                });
+                None
+            }
+        }
     }
 
-    pub fn auxiliary_polling_interval(&self) -> Duration {
-        self.auxiliary_polling_interval
+    /// Parses a value into a non-negative number and records an error if that is not possible.
+    fn number(&mut self, name: &str, file_key: &str, value: String) -> Option<u64> {
+        match value.parse::<u64>() {
+            Ok(number) => Some(number),
+            Err(_) => {
+                self.errors.push(ConfigError::InvalidNumber {
+                    variable: name.to_owned(),
+                    file_key: file_key.to_owned(),
+                    value,
+                });
+                None
+            }
+        }
     }
 }
 
+/// Returns the environment variables of this process. Variables that are not valid unicode are
+/// left out, as they cannot hold a valid configuration value.
+fn environment() -> HashMap<String, String> {
+    env::vars_os()
+        .filter_map(
+            |(name, value)| match (name.into_string(), value.into_string()) {
+                (Ok(name), Ok(value)) => Some((name, value)),
+                _ => None,
+            },
+        ).collect()
+}
+
 /// Parses a string of numbers into a duration in seconds.
 /// For example, if the string is "15", then the function will return a duration that represents 15
 /// seconds.
@@ -259,6 +902,38 @@ fn string_to_seconds(string: &str) -> Result<Duration, Box<Error>> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::process;
+
+    /// Returns an environment with the mandatory values that have no default.
+    fn mandatory_environment() -> HashMap<String, String> {
+        vec![
+            (
+                "MOSAIC_ORIGIN_VALIDATOR_ADDRESS",
+                "6789012345678901234567890123456789012345",
+            ),
+            (
+                "MOSAIC_AUXILIARY_VALIDATOR_ADDRESS",
+                "1234567890123456789012345678901234567890",
+            ),
+            (
+                "MOSAIC_ORIGIN_BLOCK_STORE_ADDRESS",
+                "5678901234123456789012345678901234567890",
+            ),
+            (
+                "MOSAIC_AUXILIARY_BLOCK_STORE_ADDRESS",
+                "5678901234123456789012345678901234567890",
+            ),
+            ("MOSAIC_ORIGIN_KEYSTORE_PATH", "/keys/origin.json"),
+            ("MOSAIC_AUXILIARY_KEYSTORE_PATH", "/keys/auxiliary.json"),
+        ].into_iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+    }
+
+    /// Parses the content of a configuration file.
+    fn file_config(content: &str) -> FileConfig {
+        toml::from_str(content).unwrap()
+    }
 
     #[test]
     fn the_config_reads_the_environment_variables() {
@@ -266,31 +941,33 @@ mod test {
 
         // These must be set without a fallback. Mandatory.
         env::set_var(
-            ENV_ORIGIN_VALIDATOR_ADDRESS,
+            "MOSAIC_ORIGIN_VALIDATOR_ADDRESS",
             "6789012345678901234567890123456789012345",
         );
         env::set_var(
-            ENV_AUXILIARY_VALIDATOR_ADDRESS,
+            "MOSAIC_AUXILIARY_VALIDATOR_ADDRESS",
             "1234567890123456789012345678901234567890",
         );
         env::set_var(
-            ENV_ORIGIN_BLOCK_STORE_ADDRESS,
+            "MOSAIC_ORIGIN_BLOCK_STORE_ADDRESS",
             "5678901234123456789012345678901234567890",
         );
         env::set_var(
-            ENV_AUXILIARY_BLOCK_STORE_ADDRESS,
+            "MOSAIC_AUXILIARY_BLOCK_STORE_ADDRESS",
             "5678901234123456789012345678901234567890",
         );
+        env::set_var("MOSAIC_ORIGIN_KEYSTORE_PATH", "/keys/origin.json");
+        env::set_var("MOSAIC_AUXILIARY_KEYSTORE_PATH", "/keys/auxiliary.json");
 
-        let config = Config::new();
+        let config = Config::new().unwrap();
         assert_eq!(
-            config.origin_endpoint,
-            DEFAULT_ORIGIN_ENDPOINT.to_owned(),
+            config.origin.endpoints,
+            vec![DEFAULT_ORIGIN_ENDPOINT.to_owned()],
             "Did not set the default origin endpoint when no ENV var set.",
         );
         assert_eq!(
-            config.auxiliary_endpoint,
-            DEFAULT_AUXILIARY_ENDPOINT.to_owned(),
+            config.auxiliary.endpoints,
+            vec![DEFAULT_AUXILIARY_ENDPOINT.to_owned()],
             "Did not set the default auxiliary endpoint when no ENV var set.",
         );
 
@@ -299,13 +976,15 @@ mod test {
         // and removing env variables, as rust runs test methods in parallel.
 
         let expected_origin_endpoint = "10.0.0.1";
-        env::set_var(ENV_ORIGIN_ENDPOINT, expected_origin_endpoint);
+        env::set_var("MOSAIC_ORIGIN_ENDPOINT", expected_origin_endpoint);
 
-        let config = Config::new();
+        let config = Config::new().unwrap();
         assert_eq!(
-            config.origin_endpoint, expected_origin_endpoint,
-            "Did not read the origin endpoint {}, but {} instead",
-            expected_origin_endpoint, config.origin_endpoint,
+            config.origin.endpoints,
+            vec![expected_origin_endpoint],
+            "Did not read the origin endpoint {}, but {:?} instead",
+            expected_origin_endpoint,
+            config.origin.endpoints,
         );
         assert_eq!(
             config.origin_validator_address(),
@@ -332,34 +1011,218 @@ mod test {
                 .unwrap()
         );
 
-        env::set_var(ENV_ORIGIN_ENDPOINT, "10.0.0.1");
-        let config = Config::new();
-        assert_eq!(config.origin_endpoint, "10.0.0.1");
+        env::set_var("MOSAIC_ORIGIN_ENDPOINT", "10.0.0.1");
+        let config = Config::new().unwrap();
+        assert_eq!(config.origin.endpoints, vec!["10.0.0.1"]);
         // Assert also that it does not overwrite the wrong configuration value.
         assert_eq!(
-            config.auxiliary_endpoint,
-            DEFAULT_AUXILIARY_ENDPOINT.to_owned()
+            config.auxiliary.endpoints,
+            vec![DEFAULT_AUXILIARY_ENDPOINT.to_owned()]
         );
 
         let expected_auxiliary_endpoint = "10.0.0.2";
-        env::set_var(ENV_AUXILIARY_ENDPOINT, expected_auxiliary_endpoint);
-        let config = Config::new();
+        env::set_var("MOSAIC_AUXILIARY_ENDPOINT", expected_auxiliary_endpoint);
+        let config = Config::new().unwrap();
+        assert_eq!(
+            config.origin.endpoints,
+            vec![expected_origin_endpoint],
+            "Did not read the origin endpoint {}, but {:?} instead",
+            expected_origin_endpoint,
+            config.origin.endpoints,
+        );
         assert_eq!(
-            config.origin_endpoint, expected_origin_endpoint,
-            "Did not read the origin endpoint {}, but {} instead",
-            expected_origin_endpoint, config.origin_endpoint,
+            config.auxiliary.endpoints,
+            vec![expected_auxiliary_endpoint],
+            "Did not read the auxiliary endpoint {}, but {:?} instead",
+            expected_auxiliary_endpoint,
+            config.auxiliary.endpoints,
+        );
+
+        env::remove_var("MOSAIC_ORIGIN_ENDPOINT");
+        env::remove_var("MOSAIC_AUXILIARY_ENDPOINT");
+        env::remove_var("MOSAIC_ORIGIN_VALIDATOR_ADDRESS");
+        env::remove_var("MOSAIC_AUXILIARY_VALIDATOR_ADDRESS");
+        env::remove_var("MOSAIC_ORIGIN_BLOCK_STORE_ADDRESS");
+        env::remove_var("MOSAIC_AUXILIARY_BLOCK_STORE_ADDRESS");
+        env::remove_var("MOSAIC_ORIGIN_KEYSTORE_PATH");
+        env::remove_var("MOSAIC_AUXILIARY_KEYSTORE_PATH");
+    }
+
+    #[test]
+    fn the_config_reads_a_list_of_endpoints() {
+        let mut environment = mandatory_environment();
+        environment.insert(
+            "MOSAIC_AUXILIARY_ENDPOINT".to_owned(),
+            "ws://10.0.0.2:8546, http://10.0.0.4:8545".to_owned(),
         );
+
+        let config = Config::from_sources(&FileConfig::default(), environment).unwrap();
         assert_eq!(
-            config.auxiliary_endpoint, expected_auxiliary_endpoint,
-            "Did not read the auxiliary endpoint {}, but {} instead",
-            expected_auxiliary_endpoint, config.auxiliary_endpoint,
+            config.auxiliary.endpoints,
+            vec!["ws://10.0.0.2:8546", "http://10.0.0.4:8545"]
         );
+    }
+
+    #[test]
+    fn the_config_reads_a_file() {
+        let path = env::temp_dir().join(format!(
+            "mosaic_the_config_reads_a_file_{}.toml",
+            process::id()
+        ));
+        fs::write(
+            &path,
+            r#"
+            state_path = "/var/lib/mosaic/state.json"
+
+            [origin]
+            endpoint = "http://10.0.0.3:8545"
+            validator_address = "6789012345678901234567890123456789012345"
+            block_store_address = "5678901234123456789012345678901234567890"
+            keystore_path = "/keys/origin.json"
+            polling_interval = 15
+            confirmations = 12
+            retry_delay = 250
+            gas_multiplier = 1.5
+            gas_price = "percentile:60"
+            max_gas_price = 100000000000
+            reporting_policy = "checkpoints"
+            core_address = "1234567890123456789012345678901234567890"
+
+            [auxiliary]
+            endpoints = ["ws://10.0.0.4:8546", "http://10.0.0.5:8545"]
+            validator_address = "1234567890123456789012345678901234567890"
+            block_store_address = "5678901234123456789012345678901234567890"
+            keystore_path = "/keys/auxiliary.json"
+            "#,
+        ).unwrap();
+        let file_config = FileConfig::from_file(&path);
+        fs::remove_file(&path).unwrap();
 
-        env::remove_var(ENV_ORIGIN_ENDPOINT);
-        env::remove_var(ENV_AUXILIARY_ENDPOINT);
-        env::remove_var(ENV_ORIGIN_VALIDATOR_ADDRESS);
-        env::remove_var(ENV_AUXILIARY_VALIDATOR_ADDRESS);
-        env::remove_var(ENV_ORIGIN_BLOCK_STORE_ADDRESS);
-        env::remove_var(ENV_AUXILIARY_BLOCK_STORE_ADDRESS);
+        let config = Config::from_sources(&file_config.unwrap(), HashMap::new()).unwrap();
+        assert_eq!(config.origin.endpoints, vec!["http://10.0.0.3:8545"]);
+        assert_eq!(
+            config.auxiliary.endpoints,
+            vec!["ws://10.0.0.4:8546", "http://10.0.0.5:8545"]
+        );
+        assert_eq!(config.origin_polling_interval(), Duration::from_secs(15));
+        assert_eq!(config.origin_confirmations(), 12);
+        assert_eq!(config.auxiliary_confirmations(), 0);
+        assert_eq!(config.origin_retry_delay(), Duration::from_millis(250));
+        assert_eq!(config.origin_max_retries(), 5);
+        assert_eq!(config.origin_gas_multiplier(), 1.5);
+        assert_eq!(
+            config.origin_gas_price_strategy(),
+            GasPriceStrategy::Percentile(60)
+        );
+        assert_eq!(
+            config.origin_max_gas_price(),
+            Some(U256::from(100_000_000_000u64))
+        );
+        assert_eq!(config.auxiliary_gas_multiplier(), 1.2);
+        assert_eq!(config.auxiliary_gas_price_strategy(), GasPriceStrategy::Node);
+        assert_eq!(config.auxiliary_max_gas_price(), None);
+        assert_eq!(
+            config.origin_reporting_policy(),
+            ReportingPolicy::Checkpoints
+        );
+        assert_eq!(config.auxiliary_reporting_policy(), ReportingPolicy::All);
+        assert_eq!(config.state_path(), Path::new("/var/lib/mosaic/state.json"));
+        assert_eq!(
+            config._origin_core_address,
+            Some(
+                "1234567890123456789012345678901234567890"
+                    .parse::<Address>()
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            config.origin_keystore_path(),
+            Path::new("/keys/origin.json")
+        );
+        assert_eq!(
+            config.auxiliary_keystore_path(),
+            Path::new("/keys/auxiliary.json")
+        );
+    }
+
+    #[test]
+    fn the_environment_variables_override_the_file() {
+        let file_config = file_config(
+            r#"
+            [origin]
+            polling_interval = 15
+            "#,
+        );
+        let mut environment = mandatory_environment();
+        environment.insert("MOSAIC_ORIGIN_POLLING_INTERVAL".to_owned(), "3".to_owned());
+
+        let config = Config::from_sources(&file_config, environment).unwrap();
+        assert_eq!(
+            config.origin_polling_interval(),
+            Duration::from_secs(3),
+            "The environment variable did not override the value of the configuration file."
+        );
+    }
+
+    #[test]
+    fn the_config_reports_all_problems_at_once() {
+        let file_config = file_config(
+            r#"
+            [origin]
+            validator_address = "not an address"
+            block_store_address = "5678901234123456789012345678901234567890"
+            keystore_path = "/keys/origin.json"
+            polling_interval = 0
+
+            [auxiliary]
+            validator_address = "1234567890123456789012345678901234567890"
+            keystore_path = "/keys/auxiliary.json"
+            core_address = "1234567890123456789012345678901234567890"
+            "#,
+        );
+        let environment = vec![
+            ("MOSAIC_AUXILIARY_POLLING_INTERVAL", "one"),
+            ("MOSAIC_ORIGIN_GAS_PRICE", "percentile:150"),
+        ].into_iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+
+        let errors = match Config::from_sources(&file_config, environment) {
+            Ok(_) => panic!("An invalid configuration must not be accepted."),
+            Err(errors) => errors,
+        };
+        assert_eq!(
+            errors,
+            vec![
+                ConfigError::InvalidAddress {
+                    variable: "MOSAIC_ORIGIN_VALIDATOR_ADDRESS".to_owned(),
+                    file_key: "origin.validator_address".to_owned(),
+                    value: "not an address".to_owned(),
+                },
+                ConfigError::InvalidDuration {
+                    variable: "MOSAIC_ORIGIN_POLLING_INTERVAL".to_owned(),
+                    file_key: "origin.polling_interval".to_owned(),
+                    value: "0".to_owned(),
+                },
+                ConfigError::InvalidGasPriceStrategy {
+                    variable: "MOSAIC_ORIGIN_GAS_PRICE".to_owned(),
+                    file_key: "origin.gas_price".to_owned(),
+                    value: "percentile:150".to_owned(),
+                    explanation: "Invalid percentile '150'".to_owned(),
+                },
+                ConfigError::MissingValue {
+                    variable: "MOSAIC_AUXILIARY_BLOCK_STORE_ADDRESS".to_owned(),
+                    file_key: "auxiliary.block_store_address".to_owned(),
+                },
+                ConfigError::InvalidDuration {
+                    variable: "MOSAIC_AUXILIARY_POLLING_INTERVAL".to_owned(),
+                    file_key: "auxiliary.polling_interval".to_owned(),
+                    value: "one".to_owned(),
+                },
+                ConfigError::UnsupportedKey {
+                    file_key: "auxiliary.core_address".to_owned(),
+                },
+            ]
+        );
     }
 }
@@ -14,25 +14,133 @@
 
 ///! This module manages contract instances.
 ///! To add new contract instance add a new enum type and also initialize contract in initialize method.
+use ethabi;
+use ethereum::filter::EventSubscription;
+use ethereum::types::block::{Block, Event};
 use ethereum::types::error::Error;
 use ethereum::types::error::ErrorKind;
-use ethereum::Ethereum;
+use ethereum::{Ethereum, NodeTransport};
 use std::collections::HashMap;
 use std::sync::Arc;
+use web3::contract::tokens::Tokenize;
 use web3::contract::Contract;
-use web3::transports::Http;
+use web3::types::{Address, Bytes, H256};
 use Config;
 
 /// This enum represents the type of a contract.
-#[derive(PartialEq, Eq, Hash, Debug)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 pub enum ContractType {
     OriginBlockStore,
     AuxiliaryBlockStore,
 }
 
+/// The events that the block store and the auxiliary block store emit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockStoreEvent {
+    BlockReported { block_hash: H256 },
+    BlockJustified { block_hash: H256 },
+    BlockFinalised { block_hash: H256 },
+}
+
+/// A decoded event together with the type of the contract that emitted it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractEvent {
+    pub contract_type: ContractType,
+    pub event: BlockStoreEvent,
+}
+
+/// A contract instance combines a web3 contract, which is used to query the contract, with the
+/// ABI of the contract, which is used to encode transactions that are signed locally.
+pub struct ContractInstance {
+    contract: Contract<NodeTransport>,
+    abi: ethabi::Contract,
+}
+
+impl ContractInstance {
+    /// Creates a new contract instance from a web3 contract and its JSON ABI.
+    ///
+    /// # Arguments
+    ///
+    /// * `contract` - The web3 contract.
+    /// * `abi` - ABI of contract.
+    pub fn new(contract: Contract<NodeTransport>, abi: &[u8]) -> Result<Self, Error> {
+        let abi = ethabi::Contract::load(abi).map_err(|error| {
+            Error::new(
+                ErrorKind::ContractError,
+                format!("Was not able to load contract ABI: {}", error),
+            )
+        })?;
+
+        Ok(ContractInstance { contract, abi })
+    }
+
+    /// Returns the address of the contract.
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+
+    /// Returns the web3 contract to query the contract.
+    pub fn contract(&self) -> &Contract<NodeTransport> {
+        &self.contract
+    }
+
+    /// Encodes a call of a contract function as transaction data.
+    ///
+    /// # Arguments
+    ///
+    /// * `function` - The name of the function to call.
+    /// * `params` - The parameters of the function call.
+    pub fn encode_input<P: Tokenize>(&self, function: &str, params: P) -> Result<Bytes, Error> {
+        self.abi
+            .function(function)
+            .and_then(|function| function.encode_input(&params.into_tokens()))
+            .map(Bytes)
+            .map_err(|error| {
+                Error::new(
+                    ErrorKind::ContractError,
+                    format!("Was not able to encode call of '{}': {}", function, error),
+                )
+            })
+    }
+
+    /// Returns the subscription to an event of the contract.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The name of the event.
+    pub fn subscription(&self, event: &str) -> Result<EventSubscription, Error> {
+        self.abi
+            .event(event)
+            .map(|abi_event| EventSubscription {
+                address: self.address(),
+                signature: abi_event.signature(),
+            }).map_err(|error| {
+                Error::new(
+                    ErrorKind::ContractError,
+                    format!("Contract has no event '{}': {}", event, error),
+                )
+            })
+    }
+
+    /// Decodes an event of the contract.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The raw event, emitted by this contract.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the event is not a block store event. Returns an error if the event does
+    /// not match the ABI of the contract.
+    pub fn decode_event(&self, event: &Event) -> Result<Option<BlockStoreEvent>, Error> {
+        decode_block_store_event(&self.abi, event)
+    }
+}
+
 /// This struct stores a map of contract types to instances.
+#[derive(Default)]
 pub struct ContractRegistry {
-    contracts: HashMap<ContractType, Arc<Contract<Http>>>,
+    contracts: HashMap<ContractType, Arc<ContractInstance>>,
 }
 
 impl ContractRegistry {
@@ -50,7 +158,7 @@ impl ContractRegistry {
         auxiliary: Arc<Ethereum>,
         config: &Config,
     ) -> Result<ContractRegistry, Error> {
-        let mut contracts: HashMap<ContractType, Arc<Contract<Http>>> = HashMap::new();
+        let mut contracts: HashMap<ContractType, Arc<ContractInstance>> = HashMap::new();
         auxiliary
             .contract_instance(
                 config.origin_block_store_address(),
@@ -68,13 +176,60 @@ impl ContractRegistry {
         Ok(ContractRegistry { contracts })
     }
 
+    /// Decodes the events of a block that were emitted by contracts of the registry.
+    /// Events of other contracts are skipped. Events that cannot be decoded are logged and
+    /// skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block whose events to decode.
+    pub fn decode_events(&self, block: &Block) -> Vec<ContractEvent> {
+        block
+            .events
+            .iter()
+            .filter_map(|event| match self.decode_event(event) {
+                Ok(contract_event) => contract_event,
+                Err(error) => {
+                    warn!("Skipping event of block {:x}: {}", block.hash, error);
+                    None
+                }
+            }).collect()
+    }
+
+    /// Decodes an event if it was emitted by a contract of the registry.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The raw event.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the event was emitted by another contract or if it is not a block store
+    /// event.
+    pub fn decode_event(&self, event: &Event) -> Result<Option<ContractEvent>, Error> {
+        let contract = self
+            .contracts
+            .iter()
+            .find(|(_, instance)| instance.address() == event.address);
+
+        match contract {
+            Some((contract_type, instance)) => {
+                Ok(instance.decode_event(event)?.map(|event| ContractEvent {
+                    contract_type: *contract_type,
+                    event,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// This returns contract instance.
     /// This throws error if contract instance doesn't exist.
     ///
     /// # Arguments
     ///
     /// * `contract_type` - Type of contract.
-    pub fn get(&self, contract_type: &ContractType) -> Result<Arc<Contract<Http>>, Error> {
+    pub fn get(&self, contract_type: &ContractType) -> Result<Arc<ContractInstance>, Error> {
         match self.contracts.get(contract_type) {
             Some(instance) => Ok(Arc::clone(instance)),
             None => Err(Error::new(
@@ -87,3 +242,79 @@ impl ContractRegistry {
         }
     }
 }
+
+/// Decodes a block store event with the given ABI.
+/// The event is identified by its signature, which is the first topic.
+fn decode_block_store_event(
+    abi: &ethabi::Contract,
+    event: &Event,
+) -> Result<Option<BlockStoreEvent>, Error> {
+    let signature = match event.topics.first() {
+        Some(signature) => *signature,
+        None => return Ok(None),
+    };
+    let abi_event = match abi.events().find(|abi_event| abi_event.signature() == signature) {
+        Some(abi_event) => abi_event,
+        None => return Ok(None),
+    };
+
+    let log = abi_event
+        .parse_log(ethabi::RawLog {
+            topics: event.topics.clone(),
+            data: event.data.0.clone(),
+        }).map_err(|error| {
+            Error::new(
+                ErrorKind::ContractError,
+                format!("Was not able to decode event '{}': {}", abi_event.name, error),
+            )
+        })?;
+    let block_hash = log
+        .params
+        .into_iter()
+        .find(|param| param.name == "blockHash")
+        .and_then(|param| param.value.to_fixed_bytes())
+        .filter(|bytes| bytes.len() == 32)
+        .map(|bytes| H256::from(&bytes[..]))
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::ContractError,
+                format!("Event '{}' has no block hash", abi_event.name),
+            )
+        })?;
+
+    let event = match abi_event.name.as_str() {
+        "BlockReported" => Some(BlockStoreEvent::BlockReported { block_hash }),
+        "BlockJustified" => Some(BlockStoreEvent::BlockJustified { block_hash }),
+        "BlockFinalised" => Some(BlockStoreEvent::BlockFinalised { block_hash }),
+        _ => None,
+    };
+
+    Ok(event)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ethereum::types::block::fixtures::event;
+
+    #[test]
+    fn block_store_events_are_decoded() {
+        let abi =
+            ethabi::Contract::load(&include_bytes!("../contract/abi/BlockStore.json")[..]).unwrap();
+        let block_hash = H256::from(42);
+        let signature = abi.event("BlockFinalised").unwrap().signature();
+
+        let finalised = event(vec![signature], block_hash.to_vec());
+        assert_eq!(
+            decode_block_store_event(&abi, &finalised).unwrap(),
+            Some(BlockStoreEvent::BlockFinalised { block_hash })
+        );
+
+        let unknown = event(vec![H256::from(1)], block_hash.to_vec());
+        assert_eq!(decode_block_store_event(&abi, &unknown).unwrap(), None);
+
+        let truncated = event(vec![signature], vec![1, 2, 3]);
+        assert!(decode_block_store_event(&abi, &truncated).is_err());
+    }
+}
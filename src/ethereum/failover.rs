@@ -0,0 +1,300 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module fails over between multiple endpoints of the same chain.
+//! The health of every endpoint is checked regularly. An endpoint is unhealthy if its block height
+//! lags behind the other endpoints or if its last requests failed. Requests go to the active
+//! endpoint and fail over to the next healthy endpoint on transient errors, so that the next retry
+//! of a failed request goes to another endpoint.
+
+use futures::future;
+use futures::prelude::*;
+use jsonrpc_core as rpc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Interval};
+use web3;
+use web3::api::SubscriptionId;
+use web3::helpers::CallFuture;
+use web3::types::U256;
+use web3::{DuplexTransport, RequestId, Transport as Web3Transport};
+
+use ethereum::retry::is_transient;
+use ethereum::transport::Transport;
+use ethereum::types::{Error, ErrorKind};
+
+/// The duration in between two health checks of all endpoints.
+const HEALTH_CHECK_INTERVAL_SECONDS: u64 = 10;
+/// The maximum number of blocks that an endpoint may lag behind the best endpoint.
+const MAX_BLOCK_LAG: u64 = 5;
+/// The number of consecutive failed requests after which an endpoint is unhealthy.
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+/// The health of a single endpoint.
+#[derive(Debug, Default, Clone)]
+struct Health {
+    /// The block height that the endpoint reported in the last health check.
+    block_number: Option<u64>,
+    /// The number of requests that failed in a row.
+    consecutive_failures: u32,
+}
+
+/// The shared state of all clones of a failover transport.
+#[derive(Debug)]
+struct FailoverState {
+    /// The index of the endpoint that requests are sent to.
+    active: usize,
+    health: Vec<Health>,
+}
+
+impl FailoverState {
+    /// Returns true if the endpoint at the given index is healthy.
+    fn is_healthy(&self, index: usize) -> bool {
+        let health = &self.health[index];
+        let best_block_number = self
+            .health
+            .iter()
+            .filter_map(|health| health.block_number)
+            .max();
+        let lags_behind = match (health.block_number, best_block_number) {
+            (Some(block_number), Some(best_block_number)) => {
+                block_number + MAX_BLOCK_LAG < best_block_number
+            }
+            (None, Some(_)) => true,
+            _ => false,
+        };
+
+        health.consecutive_failures < MAX_CONSECUTIVE_FAILURES && !lags_behind
+    }
+
+    /// Switches to the next healthy endpoint after the active one. Switches to the next endpoint
+    /// if no endpoint is healthy.
+    fn fail_over(&mut self) {
+        let count = self.health.len();
+        let next = (1..count + 1)
+            .map(|offset| (self.active + offset) % count)
+            .find(|&index| self.is_healthy(index))
+            .unwrap_or((self.active + 1) % count);
+
+        self.active = next;
+    }
+}
+
+/// A transport that sends requests to one of multiple endpoints of the same chain.
+#[derive(Debug, Clone)]
+pub struct Failover {
+    endpoints: Arc<Vec<(String, Transport)>>,
+    state: Arc<Mutex<FailoverState>>,
+}
+
+impl Failover {
+    /// Creates transports to all given endpoints and starts to check their health regularly.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoints` - The addresses of nodes of the same chain, in order of preference.
+    /// * `event_loop` - The event loop that handles the connections and the health checks.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if no transport could be created.
+    pub fn new(endpoints: &[String], event_loop: &Handle) -> Result<Self, Error> {
+        let mut transports = Vec::new();
+        for endpoint in endpoints {
+            match Transport::new(endpoint, event_loop) {
+                Ok(transport) => transports.push((endpoint.clone(), transport)),
+                Err(error) => warn!("Ignoring endpoint {}: {}", endpoint, error),
+            }
+        }
+        if transports.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NodeError,
+                format!("Could not connect to any of {:?}", endpoints),
+            ));
+        }
+
+        let failover = Failover {
+            state: Arc::new(Mutex::new(FailoverState {
+                active: 0,
+                health: vec![Health::default(); transports.len()],
+            })),
+            endpoints: Arc::new(transports),
+        };
+        if failover.endpoints.len() > 1 {
+            failover.check_health(event_loop)?;
+        }
+
+        Ok(failover)
+    }
+
+    /// Returns true if the active endpoint supports subscriptions with `eth_subscribe`.
+    pub fn supports_subscriptions(&self) -> bool {
+        self.active().1.supports_subscriptions()
+    }
+
+    /// Returns the index and the transport of the active endpoint.
+    fn active(&self) -> (usize, Transport) {
+        let active = self.state.lock().unwrap().active;
+        (active, self.endpoints[active].1.clone())
+    }
+
+    /// Records the result of a request to the endpoint at the given index. Fails over to another
+    /// endpoint if the endpoint became unhealthy.
+    fn record(&self, index: usize, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        if success {
+            state.health[index].consecutive_failures = 0;
+        } else {
+            state.health[index].consecutive_failures += 1;
+        }
+
+        if state.active == index && !state.is_healthy(index) {
+            state.fail_over();
+            warn!(
+                "Endpoint {} is unhealthy, failing over to {}",
+                self.endpoints[index].0, self.endpoints[state.active].0
+            );
+        }
+    }
+
+    /// Spawns a task that regularly retrieves the block height of every endpoint.
+    fn check_health(&self, event_loop: &Handle) -> Result<(), Error> {
+        let interval = Interval::new(Duration::from_secs(HEALTH_CHECK_INTERVAL_SECONDS), event_loop)
+            .map_err(|error| {
+                Error::new(
+                    ErrorKind::NodeError,
+                    format!("Could not schedule health checks: {}", error),
+                )
+            })?;
+
+        let failover = self.clone();
+        let health_checks = interval
+            .map_err(|error| error!("Health check interval failed: {}", error))
+            .for_each(move |_| {
+                let block_numbers: Vec<_> = failover
+                    .endpoints
+                    .iter()
+                    .map(|(_, transport)| {
+                        CallFuture::new(transport.execute("eth_blockNumber", vec![]))
+                            .map(|number: U256| Some(number.low_u64()))
+                            .or_else(|_| Ok(None))
+                    }).collect();
+
+                let failover = failover.clone();
+                future::join_all(block_numbers).map(move |block_numbers| {
+                    let mut state = failover.state.lock().unwrap();
+                    for (index, block_number) in block_numbers.into_iter().enumerate() {
+                        let health = &mut state.health[index];
+                        match block_number {
+                            Some(_) => health.consecutive_failures = 0,
+                            None => health.consecutive_failures += 1,
+                        }
+                        health.block_number = block_number;
+                    }
+
+                    let active = state.active;
+                    if !state.is_healthy(active) {
+                        state.fail_over();
+                        warn!(
+                            "Endpoint {} is unhealthy, failing over to {}",
+                            failover.endpoints[active].0, failover.endpoints[state.active].0
+                        );
+                    }
+                })
+            });
+        event_loop.spawn(health_checks);
+
+        Ok(())
+    }
+}
+
+impl web3::Transport for Failover {
+    type Out = Box<Future<Item = rpc::Value, Error = web3::Error>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.active().1.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        self.active().1.send(id, request)
+    }
+
+    /// Executes a request on the active endpoint. Fails over to the next endpoint on transient
+    /// errors, so that a retry of the request goes to another endpoint. The request is not
+    /// repeated here, `Retry` repeats it, so that every attempt counts towards its maximum number
+    /// of retries.
+    fn execute(&self, method: &str, params: Vec<rpc::Value>) -> Self::Out {
+        let failover = self.clone();
+        let (index, transport) = self.active();
+
+        Box::new(transport.execute(method, params).then(move |result| {
+            match &result {
+                Ok(_) => failover.record(index, true),
+                Err(error) if is_transient(error) => {
+                    failover.record(index, false);
+                    // Trying the next endpoint, even if the failed one is still considered
+                    // healthy.
+                    let mut state = failover.state.lock().unwrap();
+                    if state.active == index {
+                        state.fail_over();
+                    }
+                }
+                Err(_) => (),
+            }
+
+            result
+        }))
+    }
+}
+
+impl DuplexTransport for Failover {
+    type NotificationStream = <Transport as DuplexTransport>::NotificationStream;
+
+    /// Subscribes to notifications on the active endpoint.
+    fn subscribe(&self, id: &SubscriptionId) -> Self::NotificationStream {
+        self.active().1.subscribe(id)
+    }
+
+    fn unsubscribe(&self, id: &SubscriptionId) {
+        for (_, transport) in self.endpoints.iter() {
+            transport.unsubscribe(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn the_failover_skips_unhealthy_endpoints() {
+        let mut state = FailoverState {
+            active: 0,
+            health: vec![Health::default(); 3],
+        };
+        state.health[0].block_number = Some(100);
+        state.health[1].block_number = Some(90);
+        state.health[2].block_number = Some(99);
+        assert!(state.is_healthy(0));
+        assert!(!state.is_healthy(1));
+        assert!(state.is_healthy(2));
+
+        state.fail_over();
+        assert_eq!(state.active, 2);
+
+        state.health[0].consecutive_failures = MAX_CONSECUTIVE_FAILURES;
+        state.fail_over();
+        assert_eq!(state.active, 2);
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module selects the events of a block that are retrieved from the node.
+//! Only events that a reactor subscribed to are retrieved. A block without subscriptions is
+//! retrieved without any events.
+
+use std::collections::HashSet;
+use web3::types::{Address, BlockNumber, Filter, FilterBuilder, H256};
+
+use ethereum::types::Event;
+
+/// A subscription to the events with the given signature that the contract at the given address
+/// emits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EventSubscription {
+    pub address: Address,
+    /// The signature of the event, which is the first topic of its logs.
+    pub signature: H256,
+}
+
+/// An event filter matches the events of a set of subscriptions.
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    subscriptions: HashSet<EventSubscription>,
+}
+
+impl EventFilter {
+    /// Creates a new event filter that matches the events of the given subscriptions.
+    ///
+    /// # Arguments
+    ///
+    /// * `subscriptions` - The subscriptions to match.
+    pub fn new<I: IntoIterator<Item = EventSubscription>>(subscriptions: I) -> Self {
+        EventFilter {
+            subscriptions: subscriptions.into_iter().collect(),
+        }
+    }
+
+    /// Returns true if the filter matches no events.
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    /// Returns true if the event belongs to any subscription of the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to match.
+    pub fn matches(&self, event: &Event) -> bool {
+        event.topics.first().map_or(false, |signature| {
+            self.subscriptions.contains(&EventSubscription {
+                address: event.address,
+                signature: *signature,
+            })
+        })
+    }
+
+    /// Builds a log filter of the node for a single block.
+    /// The node matches all addresses with all signatures of the filter. The logs it returns must
+    /// therefore be matched again with `matches`.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_number` - The number of the block whose logs to filter.
+    pub fn log_filter(&self, block_number: BlockNumber) -> Filter {
+        let addresses: HashSet<Address> = self
+            .subscriptions
+            .iter()
+            .map(|subscription| subscription.address)
+            .collect();
+        let signatures: HashSet<H256> = self
+            .subscriptions
+            .iter()
+            .map(|subscription| subscription.signature)
+            .collect();
+
+        FilterBuilder::default()
+            .from_block(block_number)
+            .to_block(block_number)
+            .address(addresses.into_iter().collect())
+            .topics(Some(signatures.into_iter().collect()), None, None, None)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ethereum::types::block::fixtures;
+
+    fn event(address: u64, signature: u64) -> Event {
+        Event {
+            address: Address::from(address),
+            ..fixtures::event(vec![H256::from(signature)], vec![])
+        }
+    }
+
+    #[test]
+    fn the_filter_matches_subscribed_pairs_only() {
+        let filter = EventFilter::new(vec![
+            EventSubscription {
+                address: Address::from(1),
+                signature: H256::from(10),
+            },
+            EventSubscription {
+                address: Address::from(2),
+                signature: H256::from(20),
+            },
+        ]);
+
+        assert!(filter.matches(&event(1, 10)));
+        assert!(filter.matches(&event(2, 20)));
+        // The node returns these logs, as it matches all addresses with all signatures.
+        assert!(!filter.matches(&event(1, 20)));
+        assert!(!filter.matches(&event(2, 10)));
+
+        assert!(EventFilter::default().is_empty());
+        assert!(!EventFilter::default().matches(&event(1, 10)));
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module determines the gas limit and the gas price of transactions.
+//! The gas limit is estimated by the node and raised by a safety multiplier. The gas price is
+//! determined by a configurable strategy and capped at a maximum.
+
+use futures::future::{self, Either};
+use futures::prelude::*;
+use std::cmp;
+use web3::types::{Address, BlockId, BlockNumber, Bytes, CallRequest, U256};
+use web3::Web3;
+
+use config::GasPriceStrategy;
+use ethereum::types::{Error, ErrorKind};
+use ethereum::NodeTransport;
+
+/// The number of recent blocks whose transactions are considered by the percentile strategy.
+const PERCENTILE_BLOCKS: u64 = 10;
+
+/// A gas policy defines the gas limit and the gas price of transactions.
+#[derive(Debug, Clone, Copy)]
+pub struct GasPolicy {
+    /// The factor that the estimated gas is multiplied with to get the gas limit.
+    pub multiplier: f64,
+    /// The strategy to determine the gas price.
+    pub price_strategy: GasPriceStrategy,
+    /// The maximum gas price in wei. Higher gas prices are capped.
+    pub max_price: Option<U256>,
+}
+
+impl GasPolicy {
+    /// Caps a gas price at the maximum gas price of the policy.
+    pub fn cap(&self, gas_price: U256) -> U256 {
+        match self.max_price {
+            Some(max_price) => cmp::min(gas_price, max_price),
+            None => gas_price,
+        }
+    }
+}
+
+/// Estimates the gas that a transaction uses with `eth_estimateGas` and multiplies it with the
+/// safety multiplier of the policy.
+///
+/// # Arguments
+///
+/// * `web3` - The web3 connection to the node.
+/// * `policy` - The gas policy that defines the multiplier.
+/// * `from` - The sender of the transaction.
+/// * `to` - The receiver of the transaction, e.g. a contract address.
+/// * `data` - The data of the transaction, e.g. an encoded contract call.
+pub fn estimate_gas(
+    web3: &Web3<NodeTransport>,
+    policy: GasPolicy,
+    from: Address,
+    to: Address,
+    data: Bytes,
+) -> impl Future<Item = U256, Error = Error> {
+    let request = CallRequest {
+        from: Some(from),
+        to,
+        gas: None,
+        gas_price: None,
+        value: None,
+        data: Some(data),
+    };
+
+    web3.eth()
+        .estimate_gas(request, None)
+        .map_err(|error| {
+            Error::new(
+                ErrorKind::NodeError,
+                format!("Was not able to estimate gas: {}", error),
+            )
+        }).map(move |estimated_gas| {
+            let gas = (estimated_gas.low_u64() as f64 * policy.multiplier).ceil() as u64;
+            debug!("Estimated {} gas, using {}", estimated_gas, gas);
+            U256::from(gas)
+        })
+}
+
+/// Determines the gas price according to the strategy of the policy and caps it at the maximum
+/// gas price.
+///
+/// # Arguments
+///
+/// * `web3` - The web3 connection to the node.
+/// * `policy` - The gas policy that defines the strategy.
+pub fn gas_price(
+    web3: &Web3<NodeTransport>,
+    policy: GasPolicy,
+) -> impl Future<Item = U256, Error = Error> {
+    let gas_price = match policy.price_strategy {
+        GasPriceStrategy::Fixed(gas_price) => Either::A(future::ok(gas_price)),
+        GasPriceStrategy::Node => Either::B(Either::A(node_gas_price(web3))),
+        GasPriceStrategy::Percentile(percentile) => {
+            Either::B(Either::B(percentile_gas_price(web3.clone(), percentile)))
+        }
+    };
+
+    gas_price.map(move |gas_price| policy.cap(gas_price))
+}
+
+/// Retrieves the gas price that the node suggests.
+fn node_gas_price(web3: &Web3<NodeTransport>) -> impl Future<Item = U256, Error = Error> {
+    web3.eth().gas_price().map_err(|error| {
+        Error::new(
+            ErrorKind::NodeError,
+            format!("Was not able to retrieve gas price: {}", error),
+        )
+    })
+}
+
+/// Computes a percentile of the gas prices of the transactions in the most recent blocks.
+/// Falls back to the gas price of the node if the blocks have no transactions.
+fn percentile_gas_price(
+    web3: Web3<NodeTransport>,
+    percentile: u64,
+) -> impl Future<Item = U256, Error = Error> {
+    let eth = web3.eth();
+
+    eth.block_number()
+        .and_then(move |latest| {
+            let latest = latest.low_u64();
+            let first = latest.saturating_sub(PERCENTILE_BLOCKS - 1);
+            let blocks: Vec<_> = (first..latest + 1)
+                .map(|number| eth.block_with_txs(BlockId::Number(BlockNumber::from(number))))
+                .collect();
+
+            future::join_all(blocks)
+        }).map_err(|error| {
+            Error::new(
+                ErrorKind::NodeError,
+                format!("Was not able to retrieve recent gas prices: {}", error),
+            )
+        }).and_then(move |blocks| {
+            let gas_prices = blocks
+                .into_iter()
+                .flatten()
+                .flat_map(|block| block.transactions)
+                .map(|transaction| transaction.gas_price)
+                .collect();
+
+            match percentile_of(gas_prices, percentile) {
+                Some(gas_price) => Either::A(future::ok(gas_price)),
+                None => {
+                    debug!("No recent transactions, using the gas price of the node");
+                    Either::B(node_gas_price(&web3))
+                }
+            }
+        })
+}
+
+/// Returns the given percentile of the gas prices or `None` if there are no gas prices.
+fn percentile_of(mut gas_prices: Vec<U256>, percentile: u64) -> Option<U256> {
+    if gas_prices.is_empty() {
+        return None;
+    }
+
+    gas_prices.sort();
+    let index = (gas_prices.len() - 1) * cmp::min(percentile, 100) as usize / 100;
+
+    Some(gas_prices[index])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percentiles_are_taken_of_the_sorted_gas_prices() {
+        let gas_prices: Vec<U256> = vec![5, 1, 4, 2, 3].into_iter().map(U256::from).collect();
+        assert_eq!(percentile_of(gas_prices.clone(), 0), Some(1.into()));
+        assert_eq!(percentile_of(gas_prices.clone(), 50), Some(3.into()));
+        assert_eq!(percentile_of(gas_prices, 100), Some(5.into()));
+        assert_eq!(percentile_of(vec![], 50), None);
+    }
+
+    #[test]
+    fn gas_prices_are_capped_at_the_maximum_gas_price() {
+        let policy = GasPolicy {
+            multiplier: 1.2,
+            price_strategy: GasPriceStrategy::Node,
+            max_price: Some(4.into()),
+        };
+        assert_eq!(policy.cap(5.into()), 4.into());
+        assert_eq!(policy.cap(3.into()), 3.into());
+    }
+}
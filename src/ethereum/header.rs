@@ -0,0 +1,155 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module retrieves complete block headers from a node.
+//! The block type of rust-web3 lacks the `mixHash` and the `nonce` of a header, which are needed
+//! to compute the hash of a block. Therefore, blocks are retrieved as raw JSON.
+
+use futures::prelude::*;
+use web3;
+use web3::helpers::{self, CallFuture};
+use web3::types::{BlockId, Bytes, H160, H2048, H256, H64, U128, U256};
+use web3::{Transport, Web3};
+
+use ethereum::types::{Block, Error, ErrorKind};
+use ethereum::NodeTransport;
+
+/// A block as it is returned by `eth_getBlockByHash` and `eth_getBlockByNumber`, without its
+/// transactions.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcBlock {
+    hash: Option<H256>,
+    parent_hash: H256,
+    sha3_uncles: H256,
+    miner: H160,
+    state_root: H256,
+    transactions_root: H256,
+    receipts_root: H256,
+    logs_bloom: H2048,
+    difficulty: U256,
+    total_difficulty: U256,
+    number: Option<U128>,
+    gas_limit: U256,
+    gas_used: U256,
+    timestamp: U256,
+    extra_data: Bytes,
+    mix_hash: Option<H256>,
+    nonce: Option<H64>,
+}
+
+/// Retrieves a block with all header fields from the node.
+///
+/// # Arguments
+///
+/// * `web3` - The web3 connection to the node.
+/// * `block_id` - The hash or the number of the block to retrieve.
+///
+/// # Returns
+///
+/// Returns `None` if the node does not know the block.
+pub fn retrieve(
+    web3: &Web3<NodeTransport>,
+    block_id: BlockId,
+) -> impl Future<Item = Option<RpcBlock>, Error = web3::Error> {
+    let include_transactions = helpers::serialize(&false);
+    let request = match block_id {
+        BlockId::Hash(hash) => web3.transport().execute(
+            "eth_getBlockByHash",
+            vec![helpers::serialize(&hash), include_transactions],
+        ),
+        BlockId::Number(number) => web3.transport().execute(
+            "eth_getBlockByNumber",
+            vec![helpers::serialize(&number), include_transactions],
+        ),
+    };
+
+    CallFuture::new(request)
+}
+
+impl RpcBlock {
+    /// Tries to convert a block of the node into a `Block`.
+    ///
+    /// Fails if mandatory fields are missing, e.g. because the block is still pending.
+    pub fn into_block(self) -> Result<Block, Error> {
+        Ok(Block {
+            hash: mandatory(self.hash, "hash")?,
+            parent_hash: self.parent_hash,
+            uncles_hash: self.sha3_uncles,
+            author: self.miner,
+            state_root: self.state_root,
+            transactions_root: self.transactions_root,
+            receipts_root: self.receipts_root,
+            logs_bloom: self.logs_bloom,
+            difficulty: self.difficulty,
+            total_difficulty: self.total_difficulty,
+            number: mandatory(self.number, "number")?,
+            gas_limit: self.gas_limit,
+            gas_used: self.gas_used,
+            timestamp: self.timestamp,
+            extra_data: self.extra_data,
+            mix_hash: mandatory(self.mix_hash, "mixHash")?,
+            nonce: mandatory(self.nonce, "nonce")?,
+            events: vec![],
+        })
+    }
+}
+
+/// Returns the value of a mandatory field or an error if the field is missing.
+fn mandatory<T>(value: Option<T>, field: &str) -> Result<T, Error> {
+    value.ok_or_else(|| Error::new(ErrorKind::InvalidBlock, format!("Block has no {}", field)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn the_computed_hash_matches_the_hash_of_the_node() {
+        // Block 1 of the Ethereum main net, as returned by `eth_getBlockByNumber`.
+        let json = r#"{
+            "difficulty": "0x3ff800000",
+            "extraData": "0x476574682f76312e302e302f6c696e75782f676f312e342e32",
+            "gasLimit": "0x1388",
+            "gasUsed": "0x0",
+            "hash": "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6",
+            "logsBloom": "0x00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+            "miner": "0x05a56e2d52c817161883f50c441c3228cfe54d9f",
+            "mixHash": "0x969b900de27b6ac6a67742365dd65f55a0526c41fd18e1b16f1a1215c2e66f59",
+            "nonce": "0x539bd4979fef1ec4",
+            "number": "0x1",
+            "parentHash": "0xd4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3",
+            "receiptsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+            "sha3Uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+            "size": "0x219",
+            "stateRoot": "0xd67e4d450343046425ae4271474353857ab860dbc0a1dde64b41b5cd3a532bf3",
+            "timestamp": "0x55ba4224",
+            "totalDifficulty": "0x7ff800000",
+            "transactions": [],
+            "transactionsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+            "uncles": []
+        }"#;
+
+        let rpc_block: RpcBlock = serde_json::from_str(json).unwrap();
+        let block = rpc_block.into_block().unwrap();
+
+        assert_eq!(block.hash(), block.hash);
+        assert!(block.verify_hash().is_ok());
+
+        let mut forged_block = block.clone();
+        forged_block.state_root = H256::zero();
+        assert!(forged_block.verify_hash().is_err());
+    }
+}
@@ -14,149 +14,248 @@
 
 //! This module implements the connection to an Ethereum blockchain.
 
+use futures::future::{self, Either};
 use futures::prelude::*;
+use futures::stream;
 use rpassword;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio_core::reactor::{Handle, Interval};
+use web3;
 use web3::contract::Contract;
-use web3::transports::Http;
-use web3::types::Block as Web3Block;
-use web3::types::{Address, BlockId, BlockNumber, Bytes, FilterBuilder, Log, H160, H256};
-use web3::Web3;
+use web3::helpers::CallFuture;
+use web3::types::{Address, BlockHeader, BlockId, BlockNumber, Bytes, Log, H160, U256};
+use web3::{Transport, Web3};
 
-use ethereum::types::{Block, Error, ErrorKind, Event, Signature};
+use ethereum::contract::ContractInstance;
+use ethereum::failover::Failover;
+use ethereum::filter::EventFilter;
+use ethereum::gas::GasPolicy;
+use ethereum::nonce::NonceManager;
+use ethereum::retry::{Retry, RetryPolicy};
+use ethereum::signer::Signer;
+use ethereum::transaction::{TransactionMetrics, TransactionOutcome};
+use ethereum::types::{Block, Error, ErrorKind, Event, RawTransaction, Signature};
 
 pub mod contract;
+pub mod failover;
+pub mod filter;
+pub mod gas;
+pub mod header;
+pub mod nonce;
+pub mod retry;
+pub mod signer;
+pub mod transaction;
+pub mod transport;
 pub mod types;
 
+/// The transport that connects to the nodes of a chain. Failed requests fail over to other nodes
+/// and are retried.
+pub type NodeTransport = Retry<Failover>;
+
 /// This struct stores a connection to an Ethereum node.
+#[derive(Clone)]
 pub struct Ethereum {
-    web3: Web3<Http>,
+    web3: Web3<NodeTransport>,
     validator: H160,
-    /// The password to unlock the validator account on the node.
-    password: String,
+    /// The signer signs data and transactions locally with the key of the validator.
+    /// It is only available after the keystore of the validator has been loaded.
+    signer: Option<Arc<Signer>>,
     /// The polling interval defines the duration in between two calls to the node to poll for new
     /// blocks.
     polling_interval: Duration,
+    /// The event loop that handles the connection and the tracking of sent transactions.
+    event_loop: tokio_core::reactor::Handle,
+    /// Counts the transactions sent to this chain by their outcome.
+    transaction_metrics: Arc<TransactionMetrics>,
+    /// Hands out the nonces of the transactions of the validator.
+    nonces: Arc<NonceManager>,
+    /// Defines the gas limit and the gas price of transactions.
+    gas_policy: GasPolicy,
 }
 
-trait IntoBlock {
-    fn into_block(&self) -> Result<Block, Error>;
-}
+/// A stream of the hashes or the numbers of new blocks.
+type BlockIds = Box<Stream<Item = BlockId, Error = web3::Error>>;
 
 impl Ethereum {
     /// Creates a new instance of Ethereum pointing to the given address.
-    /// Reads the password to unlock the account in the ethereum node from `stdin`.
+    /// The instance can only sign after the keystore of the validator was loaded with
+    /// `load_signer`.
     ///
     /// # Arguments
     ///
-    /// * `endpoint` - The address of an ethereum node.
+    /// * `endpoints` - The addresses of ethereum nodes of the same chain, in order of preference.
+    /// Endpoints starting with `ws://` or `wss://` connect over a WebSocket, paths to a Unix
+    /// socket over IPC, and all other endpoints over HTTP.
     /// * `validator` - The address of the validator to sign and send messages from.
     /// * `polling_interval` - The duration in between two calls to the node to poll for new blocks.
+    /// * `retry_policy` - The policy to retry failed requests to the node.
+    /// * `gas_policy` - The policy to determine the gas limit and the gas price of transactions.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the transport to the node cannot be created.
     pub fn new(
-        endpoint: &str,
+        endpoints: &[String],
         validator: H160,
         polling_interval: Duration,
+        retry_policy: RetryPolicy,
+        gas_policy: GasPolicy,
         event_loop: tokio_core::reactor::Handle,
-    ) -> Self {
-        let http = Http::with_event_loop(endpoint, &event_loop, 5)
-            .expect("Could not initialize ethereum HTTP connection");
-        let web3 = Web3::new(http);
+    ) -> Result<Self, Error> {
+        let transport = Failover::new(endpoints, &event_loop)?;
+        let web3 = Web3::new(Retry::new(transport, retry_policy, event_loop.clone()));
 
-        let password = rpassword::prompt_password_stdout(&format!(
-            "Please enter the password for account {:x}: ",
-            &validator,
-        )).unwrap();
-
-        Ethereum {
+        Ok(Ethereum {
             web3,
             validator,
-            password,
+            signer: None,
             polling_interval,
+            event_loop,
+            transaction_metrics: Arc::new(TransactionMetrics::default()),
+            nonces: Arc::new(NonceManager::default()),
+            gas_policy,
+        })
+    }
+
+    /// Loads the keystore of the validator to sign data and transactions locally.
+    /// Reads the password to decrypt the keystore from `stdin`.
+    ///
+    /// # Arguments
+    ///
+    /// * `keystore_path` - The path to the encrypted V3 keystore file of the validator.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the keystore cannot be decrypted or if it does not belong to the
+    /// validator.
+    pub fn load_signer(&mut self, keystore_path: &Path) -> Result<(), Error> {
+        let password = rpassword::prompt_password_stdout(&format!(
+            "Please enter the password for account {:x}: ",
+            &self.validator,
+        )).map_err(|error| {
+            Error::new(
+                ErrorKind::SignerError,
+                format!("Could not read password: {}", error),
+            )
+        })?;
+
+        let signer = Signer::from_keystore(keystore_path, &password)?;
+        if signer.address() != self.validator {
+            return Err(Error::new(
+                ErrorKind::SignerError,
+                format!(
+                    "The keystore at {} belongs to {:x} and not to the validator {:x}",
+                    keystore_path.display(),
+                    signer.address(),
+                    self.validator,
+                ),
+            ));
         }
+
+        self.signer = Some(Arc::new(signer));
+
+        Ok(())
     }
 
     /// Stream blocks returns a `futures::stream::Stream` of `Block`s.
     ///
-    /// Converts a stream of web3 blocks to a stream of blocks.
+    /// Converts a stream of web3 blocks to a stream of blocks. Subscribes to new heads if the
+    /// transport supports subscriptions and falls back to polling the block number otherwise or
+    /// when the subscription ends.
     ///
     /// It is the caller's responsibility to poll the stream, e.g. call `for_each` and put the
     /// future into a reactor.
-    pub fn stream_blocks(&self) -> impl Stream<Item = Block, Error = Error> {
-        // Blocks filter is a future that returns a filter.
-        let blocks_filter = self.web3.eth_filter().create_blocks_filter();
-
-        // Block hashes is a stream of block hashes.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_filter` - Selects the events that are retrieved with the blocks.
+    pub fn stream_blocks(
+        &self,
+        event_filter: EventFilter,
+    ) -> impl Stream<Item = Block, Error = Error> {
+        let web3 = self.web3.clone();
         let polling_interval = self.polling_interval;
-        let block_hashes = blocks_filter
-            .map(move |filter| filter.stream(polling_interval))
-            .flatten_stream();
+        let event_loop = self.event_loop.clone();
+
+        // Block ids is a stream of the hashes or the numbers of new blocks.
+        let block_ids: BlockIds = if self.web3.transport().inner().supports_subscriptions() {
+            let new_heads = self.web3.eth_subscribe().subscribe_new_heads().then(
+                move |subscription| -> Result<BlockIds, _> {
+                    match subscription {
+                        Ok(subscription) => {
+                            info!("Subscribed to new heads");
+                            let polling = future::lazy(move || {
+                                warn!("Subscription to new heads ended, polling instead");
+                                Ok(poll_block_numbers(web3, polling_interval, &event_loop))
+                            }).flatten_stream();
+                            Ok(Box::new(
+                                subscription
+                                    .filter_map(|header: BlockHeader| header.hash)
+                                    .map(BlockId::from)
+                                    .chain(polling),
+                            ))
+                        }
+                        Err(error) => {
+                            warn!(
+                                "Could not subscribe to new heads, polling instead: {}",
+                                error
+                            );
+                            Ok(Box::new(poll_block_numbers(
+                                web3,
+                                polling_interval,
+                                &event_loop,
+                            )))
+                        }
+                    }
+                },
+            );
+            Box::new(new_heads.flatten_stream())
+        } else {
+            Box::new(poll_block_numbers(web3, polling_interval, &event_loop))
+        };
 
-        // Web3 blocks is a stream of block futures, mapped from a stream of block hashes.
+        // Blocks is a stream of block futures, mapped from a stream of block ids.
         let web3_clone = self.web3.clone();
-        let web3_blocks = block_hashes
+        block_ids
             .map_err(|error| {
                 Error::new(
                     ErrorKind::NodeError,
                     format!("Error while streaming blocks from node: {}", error),
                 )
-            }).and_then(move |block_hash| {
-                web3_clone
-                    .eth()
-                    .block(BlockId::from(block_hash))
-                    .map_err(|error| {
-                        Error::new(
-                            ErrorKind::NodeError,
-                            format!("Was not able to retrieve block: {}", error),
-                        )
-                    })
-            });
-
-        // Returns a stream of blocks, mapped from a stream of web3 block futures.
-        let blocks = web3_blocks.and_then(|web3_block| match web3_block {
-            // Mapping web3 block Option to a Block.
-            // Wrapping in Ok() as it has to return an IntoFuture.
-            Some(web3_block) => match web3_block.into_block() {
-                Ok(block) => Ok(block),
-                Err(error) => Err(Error::new(
-                    ErrorKind::NodeError,
-                    format!("Could not convert block from web3: {}", error),
-                )),
-            },
-            None => Err(Error::new(
-                ErrorKind::NodeError,
-                "No block found".to_string(),
-            )),
-        });
+            }).and_then(move |block_id| {
+                retrieve_block(web3_clone.clone(), block_id, event_filter.clone())
+            })
+    }
 
-        // Get all events for that block from the node and add them to the block struct.
-        let web3_clone = self.web3.clone();
-        blocks.and_then(move |mut block| {
-            let block_number: u64 = block.number.low_u64();
-            let block_number = BlockNumber::from(block_number);
-
-            // Filter for all logs of the current block.
-            let filter_builder = FilterBuilder::default();
-            let log_filter = filter_builder
-                .from_block(block_number)
-                .to_block(block_number)
-                .build();
-
-            web3_clone
-                .eth()
-                .logs(log_filter)
-                .map_err(|error| {
-                    Error::new(
-                        ErrorKind::NodeError,
-                        format!("Error while retrieving logs from node: {}", error),
-                    )
-                }).map(|logs| {
-                    for log in logs {
-                        block.events.push(log.into());
-                    }
+    /// Retrieves a single block, including its events, from the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_id` - The hash or the number of the block to retrieve.
+    /// * `event_filter` - Selects the events that are retrieved with the block.
+    pub fn block(
+        &self,
+        block_id: BlockId,
+        event_filter: &EventFilter,
+    ) -> impl Future<Item = Block, Error = Error> {
+        retrieve_block(self.web3.clone(), block_id, event_filter.clone())
+    }
 
-                    block
-                })
-        })
+    /// Retrieves the number of the most recent block from the node.
+    pub fn block_number(&self) -> impl Future<Item = u64, Error = Error> {
+        self.web3
+            .eth()
+            .block_number()
+            .map(|number| number.low_u64())
+            .map_err(|error| {
+                Error::new(
+                    ErrorKind::NodeError,
+                    format!("Was not able to retrieve block number: {}", error),
+                )
+            })
     }
 
     /// Uses web3 to retrieve the accounts.
@@ -171,8 +270,8 @@ impl Ethereum {
         })
     }
 
-    /// Uses web3 to sign the given data.
-    /// Converts the signature to a blockchain signature.
+    /// Signs the given data locally with the key of the validator.
+    /// The signature is the same that `eth_sign` on the node would return.
     ///
     /// # Arguments
     ///
@@ -182,65 +281,278 @@ impl Ethereum {
     ///
     /// Returns a `Signature` of the signed data.
     pub fn sign(&self, data: Bytes) -> impl Future<Item = Signature, Error = Error> {
-        let web3_clone = self.web3.clone();
-        let validator = self.validator;
+        future::result(self.signer().map(|signer| signer.sign(&data)))
+    }
+
+    /// Signs a transaction locally, sends it to the node with `eth_sendRawTransaction`, and tracks
+    /// it until it is mined. The nonce is handed out by the nonce manager of the validator. The
+    /// gas limit and the gas price are determined by the gas policy.
+    /// Re-submits the transaction with a higher gas price if the node drops it.
+    ///
+    /// # Arguments
+    ///
+    /// * `to` - The receiver of the transaction, e.g. a contract address.
+    /// * `data` - The data of the transaction, e.g. an encoded contract call.
+    ///
+    /// # Returns
+    ///
+    /// Returns the outcome of the mined transaction, which may have been reverted.
+    pub fn submit_transaction(
+        &self,
+        to: Address,
+        data: Bytes,
+    ) -> impl Future<Item = TransactionOutcome, Error = Error> {
+        let ethereum = self.clone();
+        self.prepare_transaction(to, data)
+            .and_then(move |transaction| transaction::submit(ethereum, transaction))
+    }
+
+    /// Returns the counters of the transactions sent to this chain.
+    pub fn transaction_metrics(&self) -> Arc<TransactionMetrics> {
+        Arc::clone(&self.transaction_metrics)
+    }
+
+    /// Creates a transaction from the validator with the next nonce of the validator and the gas
+    /// limit and the gas price of the gas policy.
+    fn prepare_transaction(
+        &self,
+        to: Address,
+        data: Bytes,
+    ) -> impl Future<Item = RawTransaction, Error = Error> {
+        let nonces = Arc::clone(&self.nonces);
 
-        self.unlock_account(None).and_then(move |_| {
-            web3_clone.eth().sign(validator, data).map_err(|error| {
+        let pending_count = self
+            .web3
+            .eth()
+            .transaction_count(self.validator, Some(BlockNumber::Pending))
+            .map_err(|error| {
                 Error::new(
                     ErrorKind::NodeError,
-                    format!("Was not able to sign data: {}", error),
+                    format!("Was not able to retrieve transaction count: {}", error),
                 )
+            });
+        let gas = gas::estimate_gas(
+            &self.web3,
+            self.gas_policy,
+            self.validator,
+            to,
+            data.clone(),
+        );
+        let gas_price = gas::gas_price(&self.web3, self.gas_policy);
+
+        // The nonce is taken last, so that it is not lost if any of the requests fails.
+        pending_count
+            .join3(gas, gas_price)
+            .map(move |(pending_count, gas, gas_price)| RawTransaction {
+                nonce: nonces.take(pending_count),
+                gas_price,
+                gas,
+                to: Some(to),
+                value: U256::zero(),
+                data,
             })
-        })
     }
 
-    /// Create contract instance
+    /// Signs a transaction locally with the key of the validator for the chain id of the node.
+    /// The chain id is retrieved with `eth_chainId`. It must not be confused with the network id
+    /// of `net_version`, which differs on some chains, e.g. on private chains with a custom
+    /// `--networkid`.
     ///
     /// # Arguments
     ///
-    /// * `contract_address` -  The address of contract.
-    /// * `abi` - ABI of contract.
+    /// * `transaction` - The transaction to sign.
     ///
     /// # Returns
     ///
-    /// Returns a `contract` instance.
-    pub fn contract_instance(
+    /// Returns the signed transaction that can be sent with `eth_sendRawTransaction`.
+    fn sign_transaction(
         &self,
-        contract_address: Address,
-        abi: &[u8],
-    ) -> Result<Contract<Http>, Error> {
-        Contract::from_json(self.web3.eth(), contract_address, abi).map_err(|error| {
-            Error::new(
-                ErrorKind::NodeError,
-                format!("Was not able to instantiate contract: {}", error),
-            )
-        })
+        transaction: &RawTransaction,
+    ) -> impl Future<Item = Bytes, Error = Error> {
+        let signer = self.signer();
+        let transaction = transaction.clone();
+
+        CallFuture::new(self.web3.transport().execute("eth_chainId", vec![]))
+            .map_err(|error| {
+                Error::new(
+                    ErrorKind::NodeError,
+                    format!("Was not able to retrieve chain id: {}", error),
+                )
+            }).and_then(move |chain_id: Option<U256>| {
+                let signer = signer?;
+                let chain_id = chain_id.ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::NodeError,
+                        "Node does not report a chain id for replay protected transactions"
+                            .to_string(),
+                    )
+                })?;
+
+                Ok(signer.sign_transaction(&transaction, chain_id.low_u64()))
+            })
     }
 
-    /// Unlocks the validator account of this ethereum instance using the stored password.
+    /// Returns the signer of this instance or an error if no signer was loaded.
+    fn signer(&self) -> Result<Arc<Signer>, Error> {
+        match self.signer {
+            Some(ref signer) => Ok(Arc::clone(signer)),
+            None => Err(Error::new(
+                ErrorKind::SignerError,
+                format!("No keystore loaded for validator {:x}", self.validator),
+            )),
+        }
+    }
+
+    /// Create contract instance
     ///
     /// # Arguments
     ///
-    /// * `duration` - If given, will unlock for the duration in seconds. Otherwise for a single
-    /// transaction.
+    /// * `contract_address` -  The address of contract.
+    /// * `abi` - ABI of contract.
     ///
-    /// # Panics
+    /// # Returns
     ///
-    /// Panics if it cannot unlock the account.
-    pub fn unlock_account(&self, duration: Option<u16>) -> impl Future<Item = bool, Error = Error> {
-        self.web3
-            .personal()
-            .unlock_account(self.validator, &self.password, duration)
-            .map_err(|error| {
+    /// Returns a `ContractInstance` that can be queried and that encodes transactions.
+    pub fn contract_instance(
+        &self,
+        contract_address: Address,
+        abi: &[u8],
+    ) -> Result<ContractInstance, Error> {
+        let contract =
+            Contract::from_json(self.web3.eth(), contract_address, abi).map_err(|error| {
                 Error::new(
                     ErrorKind::NodeError,
-                    format!("Was not able to unlock account: {}", error),
+                    format!("Was not able to instantiate contract: {}", error),
                 )
-            })
+            })?;
+
+        ContractInstance::new(contract, abi)
     }
 }
 
+/// Polls the number of the latest block and returns the numbers of all blocks that were added
+/// since the last poll.
+/// Unlike a blocks filter, polling the block number keeps no state on the node. Polling
+/// therefore continues when the transport fails over to another endpoint. Failed polls are
+/// repeated after the next polling interval.
+///
+/// # Arguments
+///
+/// * `web3` - The web3 connection to the node.
+/// * `polling_interval` - The duration in between two calls to the node.
+/// * `event_loop` - The event loop that schedules the polls.
+fn poll_block_numbers<T>(
+    web3: Web3<T>,
+    polling_interval: Duration,
+    event_loop: &Handle,
+) -> impl Stream<Item = BlockId, Error = web3::Error>
+where
+    T: web3::Transport + 'static,
+{
+    let mut last_number = None;
+
+    future::result(Interval::new(polling_interval, event_loop))
+        .flatten_stream()
+        .map_err(web3::Error::from)
+        .and_then(move |_| {
+            web3.eth().block_number().then(|head| match head {
+                Ok(head) => Ok(Some(head.low_u64())),
+                Err(error) => {
+                    warn!("Could not poll the block number of the node: {}", error);
+                    Ok(None)
+                }
+            })
+        }).filter_map(move |head| {
+            head.map(|head| {
+                stream::iter_ok(new_block_numbers(&mut last_number, head))
+                    .map(|number| BlockId::Number(BlockNumber::Number(number)))
+            })
+        }).flatten()
+}
+
+/// Returns the numbers of the blocks up to the given head that follow the last returned block.
+/// Returns only the head on the first call. Returns no numbers while the head is behind the last
+/// returned block, e.g. after a fail over to an endpoint that lags behind.
+///
+/// # Arguments
+///
+/// * `last_number` - The number of the last returned block. It is updated to the head.
+/// * `head` - The number of the latest block of the node.
+fn new_block_numbers(last_number: &mut Option<u64>, head: u64) -> Range<u64> {
+    let from = match *last_number {
+        Some(last_number) if last_number >= head => return head..head,
+        Some(last_number) => last_number + 1,
+        None => head,
+    };
+    *last_number = Some(head);
+
+    from..head + 1
+}
+
+/// Retrieves a block from the node and converts it into a `Block`.
+/// Gets the events of that block that match the event filter from the node and adds them to the
+/// block struct. Does not retrieve any logs if the filter is empty.
+///
+/// # Arguments
+///
+/// * `web3` - The web3 connection to the node.
+/// * `block_id` - The hash or the number of the block to retrieve.
+/// * `event_filter` - Selects the events of the block.
+fn retrieve_block(
+    web3: Web3<NodeTransport>,
+    block_id: BlockId,
+    event_filter: EventFilter,
+) -> impl Future<Item = Block, Error = Error> {
+    let rpc_block = header::retrieve(&web3, block_id).map_err(|error| {
+        Error::new(
+            ErrorKind::NodeError,
+            format!("Was not able to retrieve block: {}", error),
+        )
+    });
+
+    // Mapping the block Option of the node to a Block.
+    let block = rpc_block.and_then(|rpc_block| match rpc_block {
+        Some(rpc_block) => rpc_block.into_block(),
+        None => Err(Error::new(
+            ErrorKind::NodeError,
+            "No block found".to_string(),
+        )),
+    });
+
+    block.and_then(move |mut block| {
+        if event_filter.is_empty() {
+            return Either::A(future::ok(block));
+        }
+
+        let block_number: u64 = block.number.low_u64();
+        let log_filter = event_filter.log_filter(BlockNumber::from(block_number));
+
+        Either::B(
+            web3.eth()
+                .logs(log_filter)
+                .map_err(|error| {
+                    Error::new(
+                        ErrorKind::NodeError,
+                        format!("Error while retrieving logs from node: {}", error),
+                    )
+                }).map(move |logs| {
+                    // Logs are filtered by number. After a chain reorganisation, the node may
+                    // return logs of another block with the same number.
+                    for log in logs {
+                        if log.block_hash.map_or(true, |hash| hash == block.hash) {
+                            let event: Event = log.into();
+                            if event_filter.matches(&event) {
+                                block.events.push(event);
+                            }
+                        }
+                    }
+
+                    block
+                }),
+        )
+    })
+}
+
 impl From<Log> for Event {
     fn from(log: Log) -> Event {
         Event {
@@ -259,46 +571,72 @@ impl From<Log> for Event {
     }
 }
 
-impl<TX> IntoBlock for Web3Block<TX> {
-    /// Tries to convert a web3 block into a `Block`.
-    ///
-    /// Fails if mandatory fields are missing.
-    fn into_block(&self) -> Result<Block, Error> {
-        Ok(Block {
-            hash: match self.hash {
-                Some(hash) => hash,
-                None => {
-                    return Err(Error::new(
-                        ErrorKind::InvalidBlock,
-                        "Block has no hash".to_string(),
-                    ));
-                }
-            },
-            parent_hash: self.parent_hash,
-            uncles_hash: self.uncles_hash,
-            author: self.author,
-            state_root: self.state_root,
-            transactions_root: self.transactions_root,
-            receipts_root: self.transactions_root,
-            logs_bloom: self.logs_bloom,
-            total_difficulty: self.total_difficulty,
-            number: match self.number {
-                Some(number) => number,
+#[cfg(test)]
+mod test {
+    use super::*;
+    use jsonrpc_core as rpc;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use tokio_core::reactor::Core;
+    use web3::helpers;
+    use web3::RequestId;
+
+    /// A transport to two endpoints that answers `eth_blockNumber` with the given heads of the
+    /// active endpoint. The first endpoint becomes unreachable once it has no heads left, and the
+    /// transport fails over to the second endpoint.
+    #[derive(Debug, Clone)]
+    struct Endpoints {
+        heads: Rc<RefCell<Vec<VecDeque<u64>>>>,
+        active: Rc<RefCell<usize>>,
+    }
+
+    impl web3::Transport for Endpoints {
+        type Out = Box<Future<Item = rpc::Value, Error = web3::Error>>;
+
+        fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+            (0, helpers::build_request(0, method, params))
+        }
+
+        fn send(&self, _id: RequestId, request: rpc::Call) -> Self::Out {
+            match request {
+                rpc::Call::MethodCall(ref call) if call.method == "eth_blockNumber" => (),
+                _ => panic!("Unexpected request {:?}", request),
+            }
+
+            let mut active = self.active.borrow_mut();
+            match self.heads.borrow_mut()[*active].pop_front() {
+                Some(head) => Box::new(future::ok(helpers::serialize(&U256::from(head)))),
                 None => {
-                    return Err(Error::new(
-                        ErrorKind::InvalidBlock,
-                        "Block has no number".to_string(),
-                    ));
+                    *active += 1;
+                    Box::new(future::err(web3::ErrorKind::Unreachable.into()))
                 }
-            },
-            gas_limit: self.gas_limit,
-            gas_used: self.gas_used,
-            timestamp: self.timestamp,
-            extra_data: self.extra_data.clone(),
-            //This information is not available in rust-web3 block.
-            mix_data: H256::from(0),
-            nonce: self.difficulty,
-            events: vec![],
-        })
+            }
+        }
+    }
+
+    #[test]
+    fn polling_continues_after_a_fail_over() {
+        let mut core = Core::new().unwrap();
+        let endpoints = Endpoints {
+            heads: Rc::new(RefCell::new(vec![
+                vec![10, 11].into_iter().collect(),
+                // The second endpoint lags behind the first one at first.
+                vec![10, 13].into_iter().collect(),
+            ])),
+            active: Rc::new(RefCell::new(0)),
+        };
+
+        let block_ids = poll_block_numbers(
+            Web3::new(endpoints),
+            Duration::from_millis(1),
+            &core.handle(),
+        );
+        let block_ids = core.run(block_ids.take(4).collect()).unwrap();
+
+        let expected: Vec<BlockId> = (10..14)
+            .map(|number| BlockId::Number(BlockNumber::Number(number)))
+            .collect();
+        assert_eq!(block_ids, expected);
     }
 }
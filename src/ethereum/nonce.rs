@@ -0,0 +1,148 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module hands out the nonces of the transactions of an account.
+//! Transactions that are sent concurrently get distinct nonces. Nonces of transactions that were
+//! never sent leave a gap, which would block all later transactions. These nonces are handed out
+//! again first, so that the gap is filled by the next transaction.
+//! Nonces of transactions that may have been sent, but that are no longer tracked, stay reserved
+//! until the transaction count of the node shows whether they were used.
+
+use std::cmp;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use web3::types::U256;
+
+/// The nonce manager of a single account.
+#[derive(Debug, Default)]
+pub struct NonceManager {
+    state: Mutex<NonceState>,
+}
+
+#[derive(Debug, Default)]
+struct NonceState {
+    /// The nonce after the highest nonce that was handed out. `None` until the first nonce was
+    /// handed out.
+    next: Option<U256>,
+    /// Nonces that were handed out but whose transactions were never sent.
+    gaps: BTreeSet<U256>,
+    /// Nonces whose transactions may have been sent, but are no longer tracked.
+    abandoned: BTreeSet<U256>,
+}
+
+impl NonceManager {
+    /// Hands out the next nonce. Fills gaps first.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending_count` - The transaction count of the account including pending transactions,
+    /// as the node reports it with `eth_getTransactionCount`. Nonces below it were already used,
+    /// e.g. by transactions that were sent by another client.
+    pub fn take(&self, pending_count: U256) -> U256 {
+        let mut state = self.state.lock().unwrap();
+
+        // Abandoned nonces from the pending count on are unknown to the node, their transactions
+        // were dropped.
+        let dropped = state.abandoned.split_off(&pending_count);
+        state.gaps.extend(dropped);
+
+        // Gaps below the pending count were filled in the meantime.
+        state.gaps = state.gaps.split_off(&pending_count);
+        if let Some(&gap) = state.gaps.iter().next() {
+            state.gaps.remove(&gap);
+            debug!("Filling nonce gap {}", gap);
+            return gap;
+        }
+
+        let nonce = match state.next {
+            Some(next) => cmp::max(next, pending_count),
+            None => pending_count,
+        };
+        state.next = Some(nonce + U256::one());
+
+        nonce
+    }
+
+    /// Returns a nonce whose transaction was not sent, so that it is handed out again.
+    ///
+    /// # Arguments
+    ///
+    /// * `nonce` - The nonce of the transaction.
+    pub fn release(&self, nonce: U256) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.next == Some(nonce + U256::one()) {
+            state.next = Some(nonce);
+        } else {
+            warn!("Nonce {} was not used, leaving a gap", nonce);
+            state.gaps.insert(nonce);
+        }
+    }
+
+    /// Keeps a nonce reserved whose transaction may have been sent, but is no longer tracked.
+    /// The nonce is handed out again once the pending transaction count of the node shows that
+    /// the transaction was dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `nonce` - The nonce of the transaction.
+    pub fn abandon(&self, nonce: U256) {
+        warn!("Nonce {} may be pending, reserving it", nonce);
+        self.state.lock().unwrap().abandoned.insert(nonce);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn the_nonce_manager_fills_gaps() {
+        let nonces = NonceManager::default();
+        assert_eq!(nonces.take(5.into()), 5.into());
+        assert_eq!(nonces.take(5.into()), 6.into());
+        assert_eq!(nonces.take(6.into()), 7.into());
+
+        // The last nonce is simply handed out again.
+        nonces.release(7.into());
+        assert_eq!(nonces.take(7.into()), 7.into());
+
+        // The node counts pending transactions only up to the gap.
+        nonces.release(6.into());
+        assert_eq!(nonces.take(6.into()), 6.into());
+        assert_eq!(nonces.take(6.into()), 8.into());
+
+        // Gaps that another client filled are skipped.
+        nonces.release(6.into());
+        assert_eq!(nonces.take(12.into()), 12.into());
+        assert_eq!(nonces.take(12.into()), 13.into());
+    }
+
+    #[test]
+    fn abandoned_nonces_stay_reserved_while_they_are_pending() {
+        let nonces = NonceManager::default();
+        assert_eq!(nonces.take(5.into()), 5.into());
+        assert_eq!(nonces.take(5.into()), 6.into());
+        assert_eq!(nonces.take(5.into()), 7.into());
+
+        // The transactions with the nonces 5 and 6 are no longer tracked, but pending.
+        nonces.abandon(5.into());
+        nonces.abandon(6.into());
+        assert_eq!(nonces.take(7.into()), 8.into());
+
+        // The node dropped the transaction with nonce 6 later.
+        assert_eq!(nonces.take(6.into()), 6.into());
+        assert_eq!(nonces.take(6.into()), 9.into());
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module retries failed requests to a node with an exponential backoff.
+//! Only transient errors, e.g. timeouts or refused connections, are retried. Permanent errors,
+//! e.g. errors returned by the node, are returned immediately.
+
+use futures::future::{self, Either, Loop};
+use futures::prelude::*;
+use jsonrpc_core as rpc;
+use rand::{thread_rng, Rng};
+use std::cmp;
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
+use web3;
+use web3::api::SubscriptionId;
+use web3::{DuplexTransport, RequestId};
+
+/// The maximum delay in between two attempts.
+const MAX_RETRY_DELAY_SECONDS: u64 = 60;
+
+/// A retry policy defines how often and when failed requests are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// The maximum number of retries after the first attempt failed.
+    pub max_retries: u32,
+    /// The delay before the first retry. It doubles with every further retry.
+    pub initial_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the delay before the given retry. The delay grows exponentially and has a random
+    /// jitter of up to half of the delay, so that many clients do not retry at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - The number of the retry, starting at 0.
+    fn delay(&self, retry: u32) -> Duration {
+        let initial_delay = duration_to_millis(self.initial_delay);
+        let delay = initial_delay
+            .checked_mul(1 << cmp::min(retry, 16))
+            .unwrap_or(u64::max_value());
+        let delay = cmp::min(delay, MAX_RETRY_DELAY_SECONDS * 1000);
+        let jitter = thread_rng().gen_range(0, delay / 2 + 1);
+
+        Duration::from_millis(delay + jitter)
+    }
+}
+
+/// A transport that retries failed requests of an inner transport according to a retry policy.
+#[derive(Debug, Clone)]
+pub struct Retry<T> {
+    inner: T,
+    policy: RetryPolicy,
+    event_loop: Handle,
+}
+
+impl<T> Retry<T> {
+    /// Wraps a transport so that its failed requests are retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The transport whose requests to retry.
+    /// * `policy` - The policy that defines how often and when to retry.
+    /// * `event_loop` - The event loop that handles the delays in between two attempts.
+    pub fn new(inner: T, policy: RetryPolicy, event_loop: Handle) -> Self {
+        Retry {
+            inner,
+            policy,
+            event_loop,
+        }
+    }
+
+    /// Returns the wrapped transport.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> web3::Transport for Retry<T>
+where
+    T: web3::Transport + 'static,
+{
+    type Out = Box<Future<Item = rpc::Value, Error = web3::Error>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.inner.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        Box::new(self.inner.send(id, request))
+    }
+
+    /// Executes a request and retries it on transient errors. All requests of the web3 API go
+    /// through this method.
+    fn execute(&self, method: &str, params: Vec<rpc::Value>) -> Self::Out {
+        let inner = self.inner.clone();
+        let method = method.to_string();
+        let policy = self.policy;
+        let event_loop = self.event_loop.clone();
+
+        Box::new(future::loop_fn(0, move |retry| {
+            let method = method.clone();
+            let event_loop = event_loop.clone();
+            inner
+                .execute(&method, params.clone())
+                .then(move |result| match result {
+                    Ok(value) => Either::A(future::ok(Loop::Break(value))),
+                    Err(error) => {
+                        if retry >= policy.max_retries || !is_transient(&error) {
+                            return Either::A(future::err(error));
+                        }
+
+                        let delay = policy.delay(retry);
+                        warn!(
+                            "Request {} to node failed, retrying in {} ms: {}",
+                            method,
+                            duration_to_millis(delay),
+                            error
+                        );
+                        Either::B(
+                            future::result(Timeout::new(delay, &event_loop))
+                                .flatten()
+                                .map(move |_| Loop::Continue(retry + 1))
+                                .from_err(),
+                        )
+                    }
+                })
+        }))
+    }
+}
+
+impl<T> DuplexTransport for Retry<T>
+where
+    T: DuplexTransport + 'static,
+{
+    type NotificationStream = T::NotificationStream;
+
+    fn subscribe(&self, id: &SubscriptionId) -> Self::NotificationStream {
+        self.inner.subscribe(id)
+    }
+
+    fn unsubscribe(&self, id: &SubscriptionId) {
+        self.inner.unsubscribe(id)
+    }
+}
+
+/// Returns true if the error is transient, i.e. if the request may succeed when it is repeated.
+pub fn is_transient(error: &web3::Error) -> bool {
+    match error.kind() {
+        web3::ErrorKind::Io(_) | web3::ErrorKind::Unreachable | web3::ErrorKind::Transport(_) => {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returns the number of whole milliseconds of a duration.
+fn duration_to_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn the_retry_delay_grows_exponentially() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_delay: Duration::from_millis(100),
+        };
+
+        for retry in 0..4 {
+            let delay = duration_to_millis(policy.delay(retry));
+            let expected = 100 * (1 << retry);
+            assert!(delay >= expected && delay <= expected + expected / 2);
+        }
+
+        let delay = duration_to_millis(policy.delay(100));
+        assert!(delay <= MAX_RETRY_DELAY_SECONDS * 1500);
+
+        assert!(is_transient(&web3::ErrorKind::Unreachable.into()));
+        assert!(!is_transient(
+            &web3::ErrorKind::Decoder("invalid".to_string()).into()
+        ));
+    }
+}
@@ -0,0 +1,430 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module implements local signing with a key from an encrypted V3 JSON keystore file.
+//! The keystore is decrypted once and the secret key is kept in memory, so that the ethereum node
+//! does not need to have the account unlocked.
+
+use crypto::aes::{self, KeySize};
+use crypto::hmac::Hmac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::scrypt::{scrypt, ScryptParams};
+use crypto::sha2::Sha256;
+use rustc_hex::FromHex;
+use secp256k1::{self, Message, PublicKey, SecretKey};
+use serde_json;
+use std::fs::File;
+use std::path::Path;
+use tiny_keccak::Keccak;
+use web3::types::{Address, Bytes, H256};
+
+use ethereum::types::{Error, ErrorKind, RawTransaction, Signature};
+
+/// The prefix that is prepended to messages before signing them, as done by `eth_sign`.
+const MESSAGE_PREFIX: &str = "\x19Ethereum Signed Message:\n";
+
+/// The bounds of the length of the derived key. The key must be long enough to hold the cipher key
+/// and the MAC key. Upper bounds keep malformed keystores from allocating huge amounts of memory.
+const MIN_DERIVED_KEY_LENGTH: usize = 32;
+const MAX_DERIVED_KEY_LENGTH: usize = 64;
+
+/// The maximum memory in bytes that scrypt may use to derive a key, which is `128 * n * r`. It
+/// allows the parameters of geth's standard keystores (`n = 2^18`, `r = 8`).
+const MAX_SCRYPT_MEMORY: u64 = 256 * 1024 * 1024;
+/// The maximum parallelisation parameter of scrypt.
+const MAX_SCRYPT_P: u32 = 16;
+
+/// A signer holds the decrypted secret key of an account and signs data and transactions with it.
+pub struct Signer {
+    address: Address,
+    secret_key: SecretKey,
+}
+
+/// The JSON representation of a V3 keystore file.
+#[derive(Deserialize)]
+struct KeyStore {
+    version: u8,
+    crypto: KeyStoreCrypto,
+}
+
+#[derive(Deserialize)]
+struct KeyStoreCrypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: String,
+    kdf: String,
+    kdfparams: KdfParams,
+    mac: String,
+}
+
+#[derive(Deserialize)]
+struct CipherParams {
+    iv: String,
+}
+
+/// Parameters of the key derivation function.
+/// Scrypt uses `n`, `r`, and `p`. PBKDF2 uses `c` and `prf`.
+#[derive(Deserialize)]
+struct KdfParams {
+    dklen: usize,
+    salt: String,
+    n: Option<u32>,
+    r: Option<u32>,
+    p: Option<u32>,
+    c: Option<u32>,
+    prf: Option<String>,
+}
+
+impl Signer {
+    /// Reads a V3 keystore file and decrypts the secret key with the given password.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the keystore file.
+    /// * `password` - The password that the keystore is encrypted with.
+    ///
+    /// # Returns
+    ///
+    /// Returns a signer that signs with the decrypted key. Returns an error if the file cannot be
+    /// read, the password is wrong, or the keystore uses an unsupported cipher or kdf.
+    pub fn from_keystore(path: &Path, password: &str) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|error| {
+            Error::new(
+                ErrorKind::SignerError,
+                format!("Could not open keystore file {}: {}", path.display(), error),
+            )
+        })?;
+        let key_store: KeyStore = serde_json::from_reader(file).map_err(|error| {
+            Error::new(
+                ErrorKind::SignerError,
+                format!(
+                    "Could not parse keystore file {}: {}",
+                    path.display(),
+                    error
+                ),
+            )
+        })?;
+
+        Self::from_key_store(&key_store, password)
+    }
+
+    /// Decrypts the secret key of a parsed keystore.
+    fn from_key_store(key_store: &KeyStore, password: &str) -> Result<Self, Error> {
+        if key_store.version != 3 {
+            return Err(Error::new(
+                ErrorKind::SignerError,
+                format!("Unsupported keystore version {}", key_store.version),
+            ));
+        }
+
+        let crypto = &key_store.crypto;
+        if crypto.cipher != "aes-128-ctr" {
+            return Err(Error::new(
+                ErrorKind::SignerError,
+                format!("Unsupported keystore cipher {}", crypto.cipher),
+            ));
+        }
+
+        let derived_key = derive_key(&crypto.kdf, &crypto.kdfparams, password)?;
+        let ciphertext = from_hex(&crypto.ciphertext)?;
+        let expected_mac = from_hex(&crypto.mac)?;
+        let mut mac_input = derived_key[16..32].to_vec();
+        mac_input.extend_from_slice(&ciphertext);
+        if keccak256(&mac_input)[..] != expected_mac[..] {
+            return Err(Error::new(
+                ErrorKind::SignerError,
+                "Could not decrypt keystore, the password is wrong".to_string(),
+            ));
+        }
+
+        let iv = from_hex(&crypto.cipherparams.iv)?;
+        let mut secret = vec![0u8; ciphertext.len()];
+        aes::ctr(KeySize::KeySize128, &derived_key[0..16], &iv).process(&ciphertext, &mut secret);
+
+        Self::from_secret(&secret)
+    }
+
+    /// Creates a signer from a raw 32 bytes secret key.
+    pub fn from_secret(secret: &[u8]) -> Result<Self, Error> {
+        let secret_key = SecretKey::parse_slice(secret).map_err(|error| {
+            Error::new(
+                ErrorKind::SignerError,
+                format!(
+                    "The keystore does not contain a valid secret key: {:?}",
+                    error
+                ),
+            )
+        })?;
+
+        let public_key = PublicKey::from_secret_key(&secret_key).serialize();
+        // The address is the last 20 bytes of the hash of the public key without its prefix byte.
+        let address = Address::from(&keccak256(&public_key[1..])[12..]);
+
+        Ok(Signer {
+            address,
+            secret_key,
+        })
+    }
+
+    /// Returns the address of the account that this signer signs for.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Signs the given data the same way that `eth_sign` does.
+    /// The data is prefixed with the Ethereum message prefix and its length before it is hashed.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to sign.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Signature` with `r`, `s`, and `v`, where `v` is 27 or 28.
+    pub fn sign(&self, data: &Bytes) -> Signature {
+        let mut message = format!("{}{}", MESSAGE_PREFIX, data.0.len()).into_bytes();
+        message.extend_from_slice(&data.0);
+
+        let (signature, recovery_id) = self.sign_hash(&keccak256(&message));
+
+        let mut serialized = [0u8; 65];
+        serialized[0..64].copy_from_slice(&signature);
+        serialized[64] = 27 + recovery_id;
+
+        Signature::from(serialized)
+    }
+
+    /// Signs a transaction for the given chain id according to EIP-155.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to sign.
+    /// * `chain_id` - The id of the chain that the transaction will be sent to.
+    ///
+    /// # Returns
+    ///
+    /// Returns the RLP encoded signed transaction that can be sent with `eth_sendRawTransaction`.
+    pub fn sign_transaction(&self, transaction: &RawTransaction, chain_id: u64) -> Bytes {
+        let hash = keccak256(&transaction.unsigned_rlp(chain_id));
+        let (signature, recovery_id) = self.sign_hash(&hash);
+
+        let v = u64::from(recovery_id) + 35 + chain_id * 2;
+        let r = H256::from(&signature[0..32]);
+        let s = H256::from(&signature[32..64]);
+
+        Bytes(transaction.signed_rlp(v, r, s))
+    }
+
+    /// Signs a 32 bytes hash and returns the compact signature and the recovery id.
+    fn sign_hash(&self, hash: &[u8; 32]) -> ([u8; 64], u8) {
+        let message = Message::parse(hash);
+        let (signature, recovery_id) = secp256k1::sign(&message, &self.secret_key);
+
+        (signature.serialize(), recovery_id.serialize())
+    }
+}
+
+/// Derives the key to decrypt the keystore with, using the key derivation function of the keystore.
+fn derive_key(kdf: &str, params: &KdfParams, password: &str) -> Result<Vec<u8>, Error> {
+    if params.dklen < MIN_DERIVED_KEY_LENGTH || params.dklen > MAX_DERIVED_KEY_LENGTH {
+        return Err(Error::new(
+            ErrorKind::SignerError,
+            format!(
+                "The derived key of the keystore must be between {} and {} bytes, but is {}",
+                MIN_DERIVED_KEY_LENGTH, MAX_DERIVED_KEY_LENGTH, params.dklen
+            ),
+        ));
+    }
+
+    let salt = from_hex(&params.salt)?;
+    let mut derived_key = vec![0u8; params.dklen];
+
+    match kdf {
+        "scrypt" => {
+            let (n, r, p) = match (params.n, params.r, params.p) {
+                (Some(n), Some(r), Some(p)) => (n, r, p),
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::SignerError,
+                        "Scrypt parameters n, r, and p must be set".to_string(),
+                    ));
+                }
+            };
+            let log_n = n.trailing_zeros();
+            // Scrypt requires `log_n < 16 * r`, the memory bound also keeps `r` small.
+            if n < 2
+                || !n.is_power_of_two()
+                || r == 0
+                || p == 0
+                || p > MAX_SCRYPT_P
+                || u64::from(log_n) >= 16 * u64::from(r)
+                || u64::from(n) * u64::from(r) > MAX_SCRYPT_MEMORY / 128
+            {
+                return Err(Error::new(
+                    ErrorKind::SignerError,
+                    format!("Invalid scrypt parameters n={}, r={}, p={}", n, r, p),
+                ));
+            }
+            let log_n = log_n as u8;
+            scrypt(
+                password.as_bytes(),
+                &salt,
+                &ScryptParams::new(log_n, r, p),
+                &mut derived_key,
+            );
+        }
+        "pbkdf2" => {
+            match params.prf {
+                Some(ref prf) if prf == "hmac-sha256" => (),
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::SignerError,
+                        "PBKDF2 is only supported with hmac-sha256".to_string(),
+                    ));
+                }
+            }
+            let c = match params.c {
+                Some(c) => c,
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::SignerError,
+                        "PBKDF2 parameter c must be set".to_string(),
+                    ));
+                }
+            };
+            let mut mac = Hmac::new(Sha256::new(), password.as_bytes());
+            pbkdf2(&mut mac, &salt, c, &mut derived_key);
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::SignerError,
+                format!("Unsupported keystore kdf {}", kdf),
+            ));
+        }
+    }
+
+    Ok(derived_key)
+}
+
+/// Decodes a hex string without `0x` prefix as it is used in keystore files.
+fn from_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    hex.from_hex().map_err(|error| {
+        Error::new(
+            ErrorKind::SignerError,
+            format!("Invalid hex in keystore: {}", error),
+        )
+    })
+}
+
+/// Calculates the keccak256 hash of the given data.
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    Keccak::keccak256(data, &mut hash);
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rustc_hex::ToHex;
+    use web3::types::U256;
+
+    /// The PBKDF2 test vector from the Web3 Secret Storage Definition.
+    const PBKDF2_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    #[test]
+    fn the_signer_decrypts_a_keystore() {
+        let key_store: KeyStore = serde_json::from_str(PBKDF2_KEYSTORE).unwrap();
+
+        let signer = Signer::from_key_store(&key_store, "testpassword").unwrap();
+        assert_eq!(
+            signer.secret_key.serialize().to_hex(),
+            "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+        );
+        assert_eq!(
+            signer.address(),
+            "008aeeda4d805471df9b2a5b0f38a0c3bcba786b"
+                .parse::<Address>()
+                .unwrap()
+        );
+
+        assert!(
+            Signer::from_key_store(&key_store, "wrongpassword").is_err(),
+            "A keystore must not be decrypted with a wrong password."
+        );
+    }
+
+    #[test]
+    fn keystores_with_excessive_key_derivation_parameters_are_rejected() {
+        let mut key_store: KeyStore = serde_json::from_str(PBKDF2_KEYSTORE).unwrap();
+        key_store.crypto.kdfparams.dklen = usize::max_value();
+        assert!(
+            Signer::from_key_store(&key_store, "testpassword").is_err(),
+            "A derived key length above the maximum must be rejected."
+        );
+        key_store.crypto.kdfparams.dklen = 16;
+        assert!(
+            Signer::from_key_store(&key_store, "testpassword").is_err(),
+            "A derived key length below the minimum must be rejected."
+        );
+
+        key_store.crypto.kdfparams.dklen = 32;
+        key_store.crypto.kdf = "scrypt".to_string();
+        key_store.crypto.kdfparams.n = Some(1 << 30);
+        key_store.crypto.kdfparams.r = Some(8);
+        key_store.crypto.kdfparams.p = Some(1);
+        assert!(
+            Signer::from_key_store(&key_store, "testpassword").is_err(),
+            "Scrypt parameters that need too much memory must be rejected."
+        );
+    }
+
+    #[test]
+    fn the_signer_signs_transactions_according_to_eip_155() {
+        // The example transaction from EIP-155.
+        let signer = Signer::from_secret(&[0x46; 32]).unwrap();
+        let transaction = RawTransaction {
+            nonce: U256::from(9),
+            gas_price: U256::from(20_000_000_000u64),
+            gas: U256::from(21_000),
+            to: Some(Address::from([0x35; 20])),
+            value: U256::from(1_000_000_000_000_000_000u64),
+            data: Bytes(vec![]),
+        };
+
+        let signed = signer.sign_transaction(&transaction, 1);
+        assert_eq!(
+            signed.0.to_hex(),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400\
+             008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8\
+             997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+    }
+}
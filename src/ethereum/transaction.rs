@@ -0,0 +1,797 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module tracks sent transactions until they are mined.
+//! The receipts of all submissions of a transaction are polled. A transaction that the node
+//! dropped, or that stays pending for too long, is re-submitted with the same nonce and a higher
+//! gas price. Failed polls are repeated, so that a flaky node does not end the tracking of a
+//! transaction that may still be mined. The outcomes are logged and counted in the transaction
+//! metrics.
+
+use futures::future::{self, Either, Loop};
+use futures::prelude::*;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tiny_keccak::Keccak;
+use tokio_core::reactor::Timeout;
+use web3;
+use web3::types::{TransactionId, TransactionReceipt, H256, U256};
+
+use ethereum::gas::GasPolicy;
+use ethereum::nonce::NonceManager;
+use ethereum::types::{Error, ErrorKind, RawTransaction};
+use ethereum::Ethereum;
+
+/// The number of polls without a receipt after which a pending transaction is re-submitted.
+const MAX_PENDING_POLLS: u32 = 60;
+/// The maximum number of attempts to re-submit a transaction before it is given up.
+const MAX_RESUBMISSIONS: u32 = 5;
+/// The percentage by which the gas price is raised on every re-submission, up to the maximum gas
+/// price.
+const GAS_PRICE_BUMP_PERCENT: u64 = 20;
+/// The percentage by which the gas price must rise at least for nodes to replace a pending
+/// transaction.
+const MIN_GAS_PRICE_BUMP_PERCENT: u64 = 10;
+/// Parts of the error messages of nodes that already know a transaction.
+const ALREADY_KNOWN_ERRORS: [&str; 3] = ["already known", "known transaction", "already imported"];
+/// Parts of the error messages of nodes that know another transaction with the same nonce, which
+/// is pending or mined.
+const NONCE_IN_USE_ERRORS: [&str; 4] = [
+    "nonce too low",
+    "nonce is too low",
+    "replacement transaction underpriced",
+    "another transaction with same nonce",
+];
+
+/// The status of a mined transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionStatus {
+    Succeeded,
+    Reverted,
+}
+
+/// The outcome of a transaction that was mined.
+#[derive(Debug, Clone)]
+pub struct TransactionOutcome {
+    /// The hash of the submission that was mined.
+    pub hash: H256,
+    pub status: TransactionStatus,
+    /// The number of the block that includes the transaction.
+    pub block_number: u64,
+    pub gas_used: U256,
+    /// The number of times that the transaction was submitted.
+    pub submissions: u32,
+}
+
+impl fmt::Display for TransactionOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "transaction {:x} {} in block {} after {} submission(s), using {} gas",
+            self.hash,
+            match self.status {
+                TransactionStatus::Succeeded => "succeeded",
+                TransactionStatus::Reverted => "reverted",
+            },
+            self.block_number,
+            self.submissions,
+            self.gas_used
+        )
+    }
+}
+
+/// Counts the transactions of a chain by their outcome.
+#[derive(Debug, Default)]
+pub struct TransactionMetrics {
+    submitted: AtomicUsize,
+    resubmitted: AtomicUsize,
+    succeeded: AtomicUsize,
+    reverted: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl fmt::Display for TransactionMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "submitted: {}, resubmitted: {}, succeeded: {}, reverted: {}, failed: {}",
+            self.submitted.load(Ordering::SeqCst),
+            self.resubmitted.load(Ordering::SeqCst),
+            self.succeeded.load(Ordering::SeqCst),
+            self.reverted.load(Ordering::SeqCst),
+            self.failed.load(Ordering::SeqCst)
+        )
+    }
+}
+
+/// How a node answered a transaction that it did not accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rejection {
+    /// The node already knows the transaction.
+    AlreadyKnown,
+    /// Another transaction with the same nonce is pending or mined, e.g. an earlier submission of
+    /// the same transaction.
+    NonceInUse,
+    /// The node rejected the transaction for another reason.
+    Rejected,
+    /// The request failed. The node may have received the transaction nevertheless.
+    Unknown,
+}
+
+/// The state of the submissions of a transaction as the node reports it.
+enum SubmissionState {
+    /// A submission was mined.
+    Mined(TransactionReceipt),
+    /// The latest submission is pending.
+    Pending,
+    /// No submission was mined and the node does not know the latest submission.
+    Dropped,
+}
+
+/// The next step of tracking a transaction after a poll without a receipt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// Wait for the next poll.
+    Wait,
+    /// Re-submit the transaction.
+    Resubmit,
+    /// Stop tracking the transaction.
+    GiveUp,
+}
+
+/// The state of a transaction while it is tracked.
+struct Submissions {
+    /// The transaction as it was last accepted by the node.
+    transaction: RawTransaction,
+    /// The hashes of all submissions of the transaction. Any of them may be mined.
+    hashes: Vec<H256>,
+    /// The number of polls since the last submission.
+    polls: u32,
+    /// The number of attempts to re-submit the transaction.
+    resubmissions: u32,
+}
+
+impl Submissions {
+    /// Starts to track a transaction after its first submission.
+    fn new(transaction: RawTransaction, hash: H256) -> Self {
+        Submissions {
+            transaction,
+            hashes: vec![hash],
+            polls: 0,
+            resubmissions: 0,
+        }
+    }
+
+    /// Decides how to continue after a poll without a receipt.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_pending` - Whether the node still knows the latest submission. Polls that failed
+    /// count as pending.
+    fn next_step(&mut self, is_pending: bool) -> Step {
+        self.polls += 1;
+        if is_pending && self.polls < MAX_PENDING_POLLS {
+            Step::Wait
+        } else if self.resubmissions >= MAX_RESUBMISSIONS {
+            Step::GiveUp
+        } else {
+            Step::Resubmit
+        }
+    }
+
+    /// Records an attempt to re-submit the transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `replacement` - The re-submitted transaction.
+    /// * `hash` - The hash of the re-submitted transaction if the node may have received it.
+    fn record_resubmission(&mut self, replacement: RawTransaction, hash: Option<H256>) {
+        self.resubmissions += 1;
+        self.polls = 0;
+
+        if let Some(hash) = hash {
+            self.transaction = replacement;
+            if !self.hashes.contains(&hash) {
+                self.hashes.push(hash);
+            }
+        }
+    }
+}
+
+/// A signed transaction and the answer of the node to it.
+struct Submission {
+    hash: H256,
+    /// How the node answered if it did not accept the transaction.
+    rejection: Option<(Rejection, web3::Error)>,
+}
+
+/// Signs and sends a transaction and tracks it until it is mined.
+/// The nonce of the transaction is only handed out again if the node rejected the transaction.
+/// If the transaction may have been sent, its nonce stays reserved.
+///
+/// # Arguments
+///
+/// * `ethereum` - The connection to the chain that the transaction is sent to.
+/// * `transaction` - The transaction to send.
+///
+/// # Returns
+///
+/// Returns the outcome of the mined transaction. Returns an error if the transaction could not
+/// be sent or if it was not mined after all re-submissions.
+pub fn submit(
+    ethereum: Ethereum,
+    transaction: RawTransaction,
+) -> impl Future<Item = TransactionOutcome, Error = Error> {
+    let metrics = ethereum.transaction_metrics();
+    let outcome_metrics = Arc::clone(&metrics);
+    let send_nonces = Arc::clone(&ethereum.nonces);
+    let tracking_nonces = Arc::clone(&ethereum.nonces);
+    let nonce = transaction.nonce;
+
+    send(&ethereum, &transaction)
+        .then(move |submission| first_submission(submission, &send_nonces, nonce))
+        .and_then(move |hash| {
+            info!("Sent transaction {:x}", hash);
+            metrics.submitted.fetch_add(1, Ordering::SeqCst);
+
+            future::loop_fn(Submissions::new(transaction, hash), move |submissions| {
+                poll(ethereum.clone(), submissions)
+            }).map_err(move |error| {
+                // Any submission may still be mined.
+                tracking_nonces.abandon(nonce);
+                error
+            })
+        }).then(move |result| {
+            match &result {
+                Ok(outcome) => match outcome.status {
+                    TransactionStatus::Succeeded => {
+                        info!("Mined {}", outcome);
+                        outcome_metrics.succeeded.fetch_add(1, Ordering::SeqCst);
+                    }
+                    TransactionStatus::Reverted => {
+                        error!("Mined {}", outcome);
+                        outcome_metrics.reverted.fetch_add(1, Ordering::SeqCst);
+                    }
+                },
+                Err(error) => {
+                    error!("Transaction failed: {}", error);
+                    outcome_metrics.failed.fetch_add(1, Ordering::SeqCst);
+                }
+            };
+            info!("Transaction metrics: {}", outcome_metrics);
+
+            result
+        })
+}
+
+/// Handles the answer of the node to the first submission of a transaction.
+/// A transaction that the node may have received is tracked, even if the request failed.
+///
+/// # Arguments
+///
+/// * `submission` - The first submission or the error if the transaction could not be signed.
+/// * `nonces` - The nonce manager that handed out the nonce of the transaction.
+/// * `nonce` - The nonce of the transaction.
+///
+/// # Returns
+///
+/// Returns the hash of the transaction to track or an error if the transaction was not sent.
+fn first_submission(
+    submission: Result<Submission, Error>,
+    nonces: &NonceManager,
+    nonce: U256,
+) -> Result<H256, Error> {
+    let (rejection, error) = match submission {
+        Ok(Submission {
+            hash,
+            rejection: None,
+        }) => return Ok(hash),
+        Ok(Submission {
+            hash,
+            rejection: Some((Rejection::Unknown, error)),
+        }) => {
+            warn!(
+                "Transaction {:x} may not have been sent, tracking it: {}",
+                hash, error
+            );
+            return Ok(hash);
+        }
+        Ok(Submission {
+            rejection: Some((rejection, error)),
+            ..
+        }) => (
+            rejection,
+            Error::new(
+                ErrorKind::NodeError,
+                format!("Was not able to send transaction: {}", error),
+            ),
+        ),
+        // The transaction could not be signed.
+        Err(error) => (Rejection::Rejected, error),
+    };
+
+    match rejection {
+        // Another transaction uses the nonce. It is reserved until it is clear whether that
+        // transaction is mined.
+        Rejection::NonceInUse => nonces.abandon(nonce),
+        // The nonce was not used, the next transaction must use it instead.
+        _ => nonces.release(nonce),
+    }
+
+    Err(error)
+}
+
+/// Waits for one polling interval and checks whether any submission of the transaction was
+/// mined. Re-submits the transaction if it was dropped or if it is pending for too long.
+fn poll(
+    ethereum: Ethereum,
+    mut submissions: Submissions,
+) -> impl Future<Item = Loop<TransactionOutcome, Submissions>, Error = Error> {
+    let delay = future::result(Timeout::new(ethereum.polling_interval, &ethereum.event_loop))
+        .flatten()
+        .map_err(|error| {
+            Error::new(
+                ErrorKind::TransactionError,
+                format!("Could not wait for transaction receipt: {}", error),
+            )
+        });
+
+    let check_ethereum = ethereum.clone();
+    let hashes = submissions.hashes.clone();
+    delay
+        .and_then(move |_| check(&check_ethereum, hashes))
+        .and_then(move |state| {
+            let is_pending = match state {
+                SubmissionState::Mined(receipt) => {
+                    return Either::A(future::ok(Loop::Break(TransactionOutcome {
+                        hash: receipt.transaction_hash,
+                        status: status(&receipt),
+                        block_number: receipt.block_number.low_u64(),
+                        gas_used: receipt.gas_used,
+                        submissions: submissions.hashes.len() as u32,
+                    })));
+                }
+                SubmissionState::Pending => true,
+                SubmissionState::Dropped => false,
+            };
+
+            let latest_hash = *submissions.hashes.last().unwrap();
+            match submissions.next_step(is_pending) {
+                Step::Wait => Either::A(future::ok(Loop::Continue(submissions))),
+                Step::Resubmit => {
+                    if is_pending {
+                        warn!("Transaction {:x} is pending for too long", latest_hash);
+                    } else {
+                        warn!("Transaction {:x} was dropped by the node", latest_hash);
+                    }
+                    Either::B(resubmit(ethereum, submissions, is_pending).map(Loop::Continue))
+                }
+                Step::GiveUp => Either::A(future::err(Error::new(
+                    ErrorKind::TransactionError,
+                    format!(
+                        "Transaction with nonce {} was not mined after {} attempts to re-submit it",
+                        submissions.transaction.nonce, submissions.resubmissions
+                    ),
+                ))),
+            }
+        })
+}
+
+/// Checks whether any submission of a transaction was mined or whether the latest submission is
+/// still pending. A check that failed counts as pending, so that it is repeated with the next
+/// poll.
+///
+/// # Arguments
+///
+/// * `ethereum` - The connection to the chain that the transaction was sent to.
+/// * `hashes` - The hashes of all submissions of the transaction, the latest one last.
+fn check(
+    ethereum: &Ethereum,
+    hashes: Vec<H256>,
+) -> impl Future<Item = SubmissionState, Error = Error> {
+    let ethereum = ethereum.clone();
+    let latest_hash = *hashes.last().unwrap();
+
+    receipts(&ethereum, hashes).then(move |receipt| match receipt {
+        Ok(Some(receipt)) => Either::A(future::ok(SubmissionState::Mined(receipt))),
+        Ok(None) => Either::B(is_pending(&ethereum, latest_hash).then(|is_pending| {
+            Ok(match is_pending {
+                Ok(true) => SubmissionState::Pending,
+                Ok(false) => SubmissionState::Dropped,
+                Err(error) => {
+                    warn!("{}, checking again with the next poll", error);
+                    SubmissionState::Pending
+                }
+            })
+        })),
+        Err(error) => {
+            warn!("{}, checking again with the next poll", error);
+            Either::A(future::ok(SubmissionState::Pending))
+        }
+    })
+}
+
+/// Re-submits a transaction with the same nonce and a higher gas price. If the node does not
+/// accept the re-submission, the earlier submissions are tracked further.
+/// If the gas price cannot be raised enough to replace the transaction, a pending transaction is
+/// not re-submitted, and a dropped transaction is re-submitted unchanged.
+///
+/// # Arguments
+///
+/// * `ethereum` - The connection to the chain that the transaction is sent to.
+/// * `submissions` - The tracked submissions of the transaction.
+/// * `is_pending` - Whether the node still knows the latest submission.
+fn resubmit(
+    ethereum: Ethereum,
+    mut submissions: Submissions,
+    is_pending: bool,
+) -> impl Future<Item = Submissions, Error = Error> {
+    let mut replacement = submissions.transaction.clone();
+    match replacement_gas_price(&ethereum.gas_policy, replacement.gas_price) {
+        Some(gas_price) => replacement.gas_price = gas_price,
+        None if is_pending => {
+            info!(
+                "Gas price {} of transaction with nonce {} cannot be raised to replace it, \
+                 waiting for it instead",
+                replacement.gas_price, replacement.nonce
+            );
+            submissions.record_resubmission(replacement, None);
+            return Either::A(future::ok(submissions));
+        }
+        None => (),
+    }
+    let metrics = ethereum.transaction_metrics();
+
+    Either::B(send(&ethereum, &replacement).then(move |submission| {
+        let nonce = replacement.nonce;
+        let hash = match submission {
+            Ok(Submission {
+                hash,
+                rejection: None,
+            }) => {
+                info!(
+                    "Re-submitted transaction with nonce {} and gas price {} as {:x}",
+                    nonce, replacement.gas_price, hash
+                );
+                metrics.resubmitted.fetch_add(1, Ordering::SeqCst);
+                Some(hash)
+            }
+            Ok(Submission {
+                hash,
+                rejection: Some((Rejection::Unknown, error)),
+            }) => {
+                warn!(
+                    "Re-submission {:x} of transaction with nonce {} may have failed: {}",
+                    hash, nonce, error
+                );
+                Some(hash)
+            }
+            Ok(Submission {
+                rejection: Some((Rejection::NonceInUse, _)),
+                ..
+            }) => {
+                info!(
+                    "Nonce {} is in use, an earlier submission is pending or mined",
+                    nonce
+                );
+                None
+            }
+            Ok(Submission {
+                rejection: Some((_, error)),
+                ..
+            }) => {
+                warn!(
+                    "Could not re-submit transaction with nonce {}: {}",
+                    nonce, error
+                );
+                None
+            }
+            Err(error) => {
+                warn!(
+                    "Could not re-submit transaction with nonce {}: {}",
+                    nonce, error
+                );
+                None
+            }
+        };
+        submissions.record_resubmission(replacement, hash);
+
+        Ok(submissions)
+    }))
+}
+
+/// Signs a transaction and sends it to the node with `eth_sendRawTransaction`. A transaction
+/// that the node already knows counts as accepted.
+///
+/// # Arguments
+///
+/// * `ethereum` - The connection to the chain that the transaction is sent to.
+/// * `transaction` - The transaction to send.
+///
+/// # Returns
+///
+/// Returns the hash of the signed transaction and how the node answered if it did not accept the
+/// transaction. Returns an error if the transaction could not be signed.
+fn send(
+    ethereum: &Ethereum,
+    transaction: &RawTransaction,
+) -> impl Future<Item = Submission, Error = Error> {
+    let eth = ethereum.web3.eth();
+
+    ethereum
+        .sign_transaction(transaction)
+        .and_then(move |signed_transaction| {
+            let hash = keccak256(&signed_transaction.0);
+            eth.send_raw_transaction(signed_transaction)
+                .then(move |result| {
+                    let rejection = match result {
+                        Ok(_) => None,
+                        Err(error) => match rejection(&error) {
+                            Rejection::AlreadyKnown => None,
+                            rejection => Some((rejection, error)),
+                        },
+                    };
+
+                    Ok(Submission { hash, rejection })
+                })
+        })
+}
+
+/// Classifies the error of a node that did not accept a transaction.
+fn rejection(error: &web3::Error) -> Rejection {
+    match error.kind() {
+        web3::ErrorKind::Rpc(error) => {
+            let message = error.message.to_lowercase();
+            let contains_any = |parts: &[&str]| parts.iter().any(|part| message.contains(part));
+
+            if contains_any(&ALREADY_KNOWN_ERRORS) {
+                Rejection::AlreadyKnown
+            } else if contains_any(&NONCE_IN_USE_ERRORS) {
+                Rejection::NonceInUse
+            } else {
+                Rejection::Rejected
+            }
+        }
+        _ => Rejection::Unknown,
+    }
+}
+
+/// Retrieves the receipts of the given transactions and returns the first existing receipt.
+fn receipts(
+    ethereum: &Ethereum,
+    hashes: Vec<H256>,
+) -> impl Future<Item = Option<TransactionReceipt>, Error = Error> {
+    let eth = ethereum.web3.eth();
+    let receipts: Vec<_> = hashes
+        .into_iter()
+        .map(|hash| eth.transaction_receipt(hash))
+        .collect();
+
+    future::join_all(receipts)
+        .map(|receipts| receipts.into_iter().flatten().next())
+        .map_err(|error| {
+            Error::new(
+                ErrorKind::NodeError,
+                format!("Was not able to retrieve transaction receipt: {}", error),
+            )
+        })
+}
+
+/// Returns true if the node still knows the given transaction.
+fn is_pending(ethereum: &Ethereum, hash: H256) -> impl Future<Item = bool, Error = Error> {
+    ethereum
+        .web3
+        .eth()
+        .transaction(TransactionId::Hash(hash))
+        .map(|transaction| transaction.is_some())
+        .map_err(|error| {
+            Error::new(
+                ErrorKind::NodeError,
+                format!("Was not able to retrieve transaction: {}", error),
+            )
+        })
+}
+
+/// Returns the status of a mined transaction. Receipts of blocks before the Byzantium fork have
+/// no status, these transactions are considered successful.
+fn status(receipt: &TransactionReceipt) -> TransactionStatus {
+    match receipt.status {
+        Some(status) if status.is_zero() => TransactionStatus::Reverted,
+        _ => TransactionStatus::Succeeded,
+    }
+}
+
+/// Hashes the given data with keccak256.
+fn keccak256(data: &[u8]) -> H256 {
+    let mut hash = [0u8; 32];
+    Keccak::keccak256(data, &mut hash);
+
+    H256::from(hash)
+}
+
+/// Returns the gas price to replace a pending transaction with, or `None` if the gas price
+/// cannot be raised by `MIN_GAS_PRICE_BUMP_PERCENT` within the maximum gas price of the policy.
+/// A replacement with a lower gas price would be rejected by the node.
+///
+/// # Arguments
+///
+/// * `gas_policy` - The gas policy that defines the maximum gas price.
+/// * `gas_price` - The gas price of the pending transaction.
+fn replacement_gas_price(gas_policy: &GasPolicy, gas_price: U256) -> Option<U256> {
+    let replacement = gas_policy.cap(bump_gas_price(gas_price));
+    let minimum = gas_price * U256::from(100 + MIN_GAS_PRICE_BUMP_PERCENT) / U256::from(100);
+
+    if replacement > gas_price && replacement >= minimum {
+        Some(replacement)
+    } else {
+        None
+    }
+}
+
+/// Raises a gas price by `GAS_PRICE_BUMP_PERCENT`, but at least by one wei.
+fn bump_gas_price(gas_price: U256) -> U256 {
+    let bumped = gas_price * U256::from(100 + GAS_PRICE_BUMP_PERCENT) / U256::from(100);
+    if bumped > gas_price {
+        bumped
+    } else {
+        gas_price + U256::one()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::GasPriceStrategy;
+    use jsonrpc_core as rpc;
+
+    fn transaction(gas_price: u64) -> RawTransaction {
+        RawTransaction {
+            nonce: 7.into(),
+            gas_price: gas_price.into(),
+            gas: 21_000.into(),
+            to: None,
+            value: U256::zero(),
+            data: Default::default(),
+        }
+    }
+
+    fn rpc_error(message: &str) -> web3::Error {
+        web3::ErrorKind::Rpc(rpc::Error {
+            code: rpc::ErrorCode::ServerError(-32000),
+            message: message.to_string(),
+            data: None,
+        }).into()
+    }
+
+    #[test]
+    fn the_gas_price_is_bumped_by_at_least_one_wei() {
+        assert_eq!(bump_gas_price(U256::from(1_000_000_000)), U256::from(1_200_000_000));
+        assert_eq!(bump_gas_price(U256::zero()), U256::one());
+        assert_eq!(bump_gas_price(U256::from(2)), U256::from(3));
+    }
+
+    #[test]
+    fn capped_gas_prices_are_not_raised_for_replacements() {
+        let policy = |max_price: Option<u64>| GasPolicy {
+            multiplier: 1.0,
+            price_strategy: GasPriceStrategy::Node,
+            max_price: max_price.map(U256::from),
+        };
+
+        assert_eq!(
+            replacement_gas_price(&policy(None), 100.into()),
+            Some(120.into())
+        );
+        assert_eq!(
+            replacement_gas_price(&policy(Some(115)), 100.into()),
+            Some(115.into())
+        );
+        // Nodes do not replace a transaction for less than 10 percent more.
+        assert_eq!(replacement_gas_price(&policy(Some(105)), 100.into()), None);
+        assert_eq!(replacement_gas_price(&policy(Some(100)), 100.into()), None);
+    }
+
+    #[test]
+    fn pending_transactions_are_resubmitted_until_they_are_given_up() {
+        let mut submissions = Submissions::new(transaction(10), H256::from(1));
+
+        for _ in 1..MAX_PENDING_POLLS {
+            assert_eq!(submissions.next_step(true), Step::Wait);
+        }
+        assert_eq!(submissions.next_step(true), Step::Resubmit);
+
+        // A dropped transaction is re-submitted immediately.
+        submissions.record_resubmission(transaction(12), Some(H256::from(2)));
+        assert_eq!(submissions.next_step(false), Step::Resubmit);
+
+        // A re-submission that the node did not accept keeps the earlier submissions.
+        submissions.record_resubmission(transaction(14), None);
+        assert_eq!(submissions.hashes, vec![H256::from(1), H256::from(2)]);
+        assert_eq!(submissions.transaction.gas_price, 12.into());
+        assert_eq!(submissions.next_step(true), Step::Wait);
+
+        // A re-submission that the node already knows is not tracked twice.
+        submissions.record_resubmission(transaction(12), Some(H256::from(2)));
+        assert_eq!(submissions.hashes.len(), 2);
+
+        for _ in submissions.resubmissions..MAX_RESUBMISSIONS {
+            submissions.record_resubmission(transaction(12), None);
+        }
+        assert_eq!(submissions.next_step(false), Step::GiveUp);
+    }
+
+    #[test]
+    fn only_nonces_of_rejected_transactions_are_released() {
+        let nonces = NonceManager::default();
+        let submission = |rejection, error| {
+            Ok(Submission {
+                hash: H256::from(1),
+                rejection: Some((rejection, error)),
+            })
+        };
+
+        // The node may have received the transaction despite the failed request.
+        let nonce = nonces.take(5.into());
+        let unknown = submission(Rejection::Unknown, web3::ErrorKind::Unreachable.into());
+        assert_eq!(first_submission(unknown, &nonces, nonce).unwrap(), H256::from(1));
+        assert_eq!(nonces.take(5.into()), 6.into());
+
+        let rejected = submission(Rejection::Rejected, rpc_error("intrinsic gas too low"));
+        assert!(first_submission(rejected, &nonces, 6.into()).is_err());
+        assert_eq!(nonces.take(6.into()), 6.into());
+
+        // The nonce stays reserved while the node counts the other transaction as pending.
+        let in_use = submission(
+            Rejection::NonceInUse,
+            rpc_error("replacement transaction underpriced"),
+        );
+        assert!(first_submission(in_use, &nonces, 6.into()).is_err());
+        assert_eq!(nonces.take(7.into()), 7.into());
+    }
+
+    #[test]
+    fn the_answers_of_nodes_are_classified() {
+        assert_eq!(
+            rejection(&rpc_error("already known")),
+            Rejection::AlreadyKnown
+        );
+        assert_eq!(
+            rejection(&rpc_error("known transaction: 0x1234")),
+            Rejection::AlreadyKnown
+        );
+        assert_eq!(
+            rejection(&rpc_error("nonce too low")),
+            Rejection::NonceInUse
+        );
+        assert_eq!(
+            rejection(&rpc_error("replacement transaction underpriced")),
+            Rejection::NonceInUse
+        );
+        assert_eq!(
+            rejection(&rpc_error(
+                "Transaction nonce is too low. Try incrementing the nonce."
+            )),
+            Rejection::NonceInUse
+        );
+        assert_eq!(
+            rejection(&rpc_error("insufficient funds for gas * price + value")),
+            Rejection::Rejected
+        );
+        assert_eq!(
+            rejection(&web3::ErrorKind::Unreachable.into()),
+            Rejection::Unknown
+        );
+    }
+}
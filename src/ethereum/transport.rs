@@ -0,0 +1,119 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module selects the transport to a node based on the endpoint.
+
+use futures::prelude::*;
+use futures::stream;
+use jsonrpc_core as rpc;
+use web3;
+use web3::api::SubscriptionId;
+use web3::transports::{Http, Ipc, WebSocket};
+use web3::{DuplexTransport, RequestId};
+
+use ethereum::types::{Error, ErrorKind};
+
+/// The maximum number of parallel requests to a node over HTTP.
+const MAX_PARALLEL_HTTP_REQUESTS: usize = 5;
+
+/// A transport connects to a node over HTTP, over a WebSocket, or over IPC, depending on the
+/// endpoint. Only WebSocket and IPC transports support subscriptions.
+#[derive(Debug, Clone)]
+pub enum Transport {
+    Http(Http),
+    WebSocket(WebSocket),
+    Ipc(Ipc),
+}
+
+impl Transport {
+    /// Creates a new transport to the given endpoint. Endpoints starting with `ws://` or `wss://`
+    /// use a WebSocket. Paths to a Unix socket, i.e. endpoints starting with `/` or `.` or ending
+    /// with `.ipc`, use IPC. All other endpoints use HTTP.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The address of an ethereum node.
+    /// * `event_loop` - The event loop that handles the connection.
+    pub fn new(endpoint: &str, event_loop: &tokio_core::reactor::Handle) -> Result<Self, Error> {
+        let transport = if endpoint.starts_with("ws://") || endpoint.starts_with("wss://") {
+            WebSocket::with_event_loop(endpoint, event_loop).map(Transport::WebSocket)
+        } else if endpoint.starts_with('/')
+            || endpoint.starts_with('.')
+            || endpoint.ends_with(".ipc")
+        {
+            Ipc::with_event_loop(endpoint, event_loop).map(Transport::Ipc)
+        } else {
+            Http::with_event_loop(endpoint, event_loop, MAX_PARALLEL_HTTP_REQUESTS)
+                .map(Transport::Http)
+        };
+
+        transport.map_err(|error| {
+            Error::new(
+                ErrorKind::NodeError,
+                format!("Could not connect to {}: {}", endpoint, error),
+            )
+        })
+    }
+
+    /// Returns true if the transport supports subscriptions with `eth_subscribe`.
+    pub fn supports_subscriptions(&self) -> bool {
+        match self {
+            Transport::Http(_) => false,
+            Transport::WebSocket(_) | Transport::Ipc(_) => true,
+        }
+    }
+}
+
+impl web3::Transport for Transport {
+    type Out = Box<Future<Item = rpc::Value, Error = web3::Error>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        match self {
+            Transport::Http(http) => http.prepare(method, params),
+            Transport::WebSocket(web_socket) => web_socket.prepare(method, params),
+            Transport::Ipc(ipc) => ipc.prepare(method, params),
+        }
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        match self {
+            Transport::Http(http) => Box::new(http.send(id, request)),
+            Transport::WebSocket(web_socket) => Box::new(web_socket.send(id, request)),
+            Transport::Ipc(ipc) => Box::new(ipc.send(id, request)),
+        }
+    }
+}
+
+impl DuplexTransport for Transport {
+    type NotificationStream = Box<Stream<Item = rpc::Value, Error = web3::Error>>;
+
+    /// Subscribes to notifications. Fails on transports that do not support subscriptions.
+    fn subscribe(&self, id: &SubscriptionId) -> Self::NotificationStream {
+        match self {
+            Transport::Http(_) => Box::new(stream::once(Err(web3::ErrorKind::Transport(
+                "HTTP does not support subscriptions".to_string(),
+            ).into()))),
+            Transport::WebSocket(web_socket) => Box::new(web_socket.subscribe(id)),
+            Transport::Ipc(ipc) => Box::new(ipc.subscribe(id)),
+        }
+    }
+
+    fn unsubscribe(&self, id: &SubscriptionId) {
+        match self {
+            Transport::Http(_) => {}
+            Transport::WebSocket(web_socket) => web_socket.unsubscribe(id),
+            Transport::Ipc(ipc) => ipc.unsubscribe(id),
+        }
+    }
+}
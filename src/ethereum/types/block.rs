@@ -15,31 +15,43 @@
 //! This module covers blocks.
 
 use rlp;
-use rlp::{Encodable, RlpStream};
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
 use std::fmt::{self, Display, Formatter};
 use tiny_keccak::Keccak;
-use web3::types::{Address, Bytes, H160, H2048, H256, U128, U256};
+use web3::types::{Address, Bytes, H160, H2048, H256, H64, U128, U256};
+
+use ethereum::types::error::{Error, ErrorKind};
+
+/// The number of fields of a block header.
+const HEADER_FIELDS: usize = 15;
 
 /// A block represents a block of a blockchain.
-#[derive(Debug)]
+/// It serializes to the JSON of a block of the Ethereum JSON-RPC API, extended by its events.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Block {
     /// The block hash of this block.
     pub hash: H256,
     pub parent_hash: H256,
+    #[serde(rename = "sha3Uncles")]
     pub uncles_hash: H256,
+    #[serde(rename = "miner")]
     pub author: H160,
     pub state_root: H256,
     pub transactions_root: H256,
     pub receipts_root: H256,
     pub logs_bloom: H2048,
+    pub difficulty: U256,
+    /// The total difficulty of the chain up to this block. It is not part of the header.
     pub total_difficulty: U256,
     pub number: U128,
     pub gas_limit: U256,
     pub gas_used: U256,
     pub timestamp: U256,
     pub extra_data: Bytes,
-    pub mix_data: H256,
-    pub nonce: U256,
+    pub mix_hash: H256,
+    pub nonce: H64,
+    #[serde(default)]
     pub events: Vec<Event>,
 }
 
@@ -55,13 +67,33 @@ impl Block {
     /// Calculate hash of block.
     pub fn hash(&self) -> H256 {
         let encoded_block = rlp::encode(self);
-        let mut res: [u8; 32] = [0; 32];
-        Keccak::keccak256(encoded_block.as_slice(), &mut res);
-        H256::from(res)
+        keccak256(&encoded_block)
+    }
+
+    /// Verifies that the hash computed from the header matches the hash of the block.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the hashes differ, e.g. because a node returned a forged header.
+    pub fn verify_hash(&self) -> Result<(), Error> {
+        let computed_hash = self.hash();
+        if computed_hash == self.hash {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidBlock,
+                format!(
+                    "Block {} has hash {:x}, but its header hashes to {:x}",
+                    self.number, self.hash, computed_hash
+                ),
+            ))
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// An event is a log of a block. It serializes to the JSON of a log of the Ethereum JSON-RPC API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Event {
     pub address: Address,
     pub topics: Vec<H256>,
@@ -72,18 +104,19 @@ pub struct Event {
     pub transaction_index: Option<U256>,
     pub log_index: Option<U256>,
     pub transaction_log_index: Option<U256>,
+    #[serde(rename = "type")]
     pub log_type: Option<String>,
     pub removed: Option<bool>,
 }
 
 impl Encodable for Block {
-    /// RLP encode block
+    /// RLP encode the header of the block in the canonical order of Ethereum headers.
     ///
     /// # Arguments
     ///
     /// * `rlp_stream` - Appendable rlp encoder.
     fn rlp_append(&self, rlp_stream: &mut RlpStream) {
-        rlp_stream.begin_list(15);
+        rlp_stream.begin_list(HEADER_FIELDS);
 
         rlp_stream.append(&self.parent_hash);
         rlp_stream.append(&self.uncles_hash);
@@ -92,13 +125,190 @@ impl Encodable for Block {
         rlp_stream.append(&self.transactions_root);
         rlp_stream.append(&self.receipts_root);
         rlp_stream.append(&self.logs_bloom);
-        rlp_stream.append(&self.total_difficulty);
+        rlp_stream.append(&self.difficulty);
         rlp_stream.append(&self.number);
         rlp_stream.append(&self.gas_limit);
         rlp_stream.append(&self.gas_used);
         rlp_stream.append(&self.timestamp);
-        rlp_stream.append(&self.hash);
-        rlp_stream.append(&self.mix_data);
+        rlp_stream.append(&self.extra_data.0);
+        rlp_stream.append(&self.mix_hash);
         rlp_stream.append(&self.nonce);
     }
 }
+
+impl Decodable for Block {
+    /// RLP decode the header of a block. The hash of the block is computed from the encoded
+    /// header. The total difficulty and the events are not part of the header and stay empty.
+    /// Bytes after the list of the header are rejected, as they would change the computed hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `rlp` - The RLP encoded header.
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.payload_info()?.total() != rlp.as_raw().len() {
+            return Err(DecoderError::RlpInconsistentLengthAndData);
+        }
+        if rlp.item_count()? != HEADER_FIELDS {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        Ok(Block {
+            hash: keccak256(rlp.as_raw()),
+            parent_hash: rlp.val_at(0)?,
+            uncles_hash: rlp.val_at(1)?,
+            author: rlp.val_at(2)?,
+            state_root: rlp.val_at(3)?,
+            transactions_root: rlp.val_at(4)?,
+            receipts_root: rlp.val_at(5)?,
+            logs_bloom: rlp.val_at(6)?,
+            difficulty: rlp.val_at(7)?,
+            total_difficulty: U256::zero(),
+            number: rlp.val_at(8)?,
+            gas_limit: rlp.val_at(9)?,
+            gas_used: rlp.val_at(10)?,
+            timestamp: rlp.val_at(11)?,
+            extra_data: Bytes(rlp.val_at(12)?),
+            mix_hash: rlp.val_at(13)?,
+            nonce: rlp.val_at(14)?,
+            events: vec![],
+        })
+    }
+}
+
+/// Returns the keccak256 hash of the given data.
+fn keccak256(data: &[u8]) -> H256 {
+    let mut res: [u8; 32] = [0; 32];
+    Keccak::keccak256(data, &mut res);
+    H256::from(res)
+}
+
+/// Blocks and events for the tests of all modules.
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+
+    /// Returns an empty block with the given number. Its hash is the number and its parent hash is
+    /// the number of its parent, so that consecutive numbers form a chain.
+    pub fn block(number: u64) -> Block {
+        Block {
+            hash: H256::from(number),
+            parent_hash: H256::from(number.saturating_sub(1)),
+            uncles_hash: H256::zero(),
+            author: H160::zero(),
+            state_root: H256::zero(),
+            transactions_root: H256::zero(),
+            receipts_root: H256::zero(),
+            logs_bloom: H2048::zero(),
+            difficulty: U256::zero(),
+            total_difficulty: U256::zero(),
+            number: U128::from(number),
+            gas_limit: U256::zero(),
+            gas_used: U256::zero(),
+            timestamp: U256::zero(),
+            extra_data: Bytes(vec![]),
+            mix_hash: H256::zero(),
+            nonce: H64::zero(),
+            events: vec![],
+        }
+    }
+
+    /// Returns an event with the given topics and data that is not part of a block yet.
+    pub fn event(topics: Vec<H256>, data: Vec<u8>) -> Event {
+        Event {
+            address: Address::zero(),
+            topics,
+            data: Bytes(data),
+            block_hash: None,
+            block_number: None,
+            transaction_hash: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::fixtures::event;
+    use super::*;
+    use serde_json;
+
+    fn block() -> Block {
+        Block {
+            hash: H256::zero(),
+            parent_hash: H256::from(1),
+            uncles_hash: H256::from(2),
+            author: H160::from(3),
+            state_root: H256::from(4),
+            transactions_root: H256::from(5),
+            receipts_root: H256::from(6),
+            logs_bloom: H2048::zero(),
+            difficulty: U256::from(131_072),
+            total_difficulty: U256::zero(),
+            number: U128::from(42),
+            gas_limit: U256::from(8_000_000),
+            gas_used: U256::from(21_000),
+            timestamp: U256::from(1_538_000_000),
+            extra_data: Bytes(b"mosaic".to_vec()),
+            mix_hash: H256::from(7),
+            nonce: H64::from(8),
+            events: vec![],
+        }
+    }
+
+    #[test]
+    fn a_decoded_header_encodes_to_the_same_rlp() {
+        let block = block();
+        let encoded = rlp::encode(&block);
+
+        let decoded: Block = rlp::decode(&encoded).unwrap();
+        assert_eq!(decoded.hash, block.hash());
+        assert_eq!(decoded.number, block.number);
+        assert_eq!(decoded.extra_data, block.extra_data);
+        assert_eq!(decoded.nonce, block.nonce);
+        assert_eq!(rlp::encode(&decoded), encoded);
+
+        assert!(rlp::decode::<Block>(&rlp::encode_list(&[H256::zero()])).is_err());
+    }
+
+    #[test]
+    fn a_header_with_trailing_bytes_is_rejected() {
+        let mut encoded = rlp::encode(&block()).to_vec();
+        encoded.push(0);
+
+        assert!(rlp::decode::<Block>(&encoded).is_err());
+    }
+
+    #[test]
+    fn a_block_serializes_to_json_rpc_conventions() {
+        let mut block = block();
+        block.events.push(Event {
+            address: Address::from(9),
+            block_hash: Some(block.hash),
+            block_number: Some(U256::from(42)),
+            log_index: Some(U256::zero()),
+            log_type: Some("mined".to_string()),
+            removed: Some(false),
+            ..event(vec![H256::from(10)], vec![1, 2])
+        });
+
+        let json = serde_json::to_value(&block).unwrap();
+        assert_eq!(json["number"], "0x2a");
+        assert_eq!(json["extraData"], "0x6d6f73616963");
+        assert_eq!(
+            json["sha3Uncles"],
+            "0x0000000000000000000000000000000000000000000000000000000000000002"
+        );
+        assert_eq!(json["nonce"], "0x0000000000000008");
+        assert_eq!(json["events"][0]["blockNumber"], "0x2a");
+        assert_eq!(json["events"][0]["data"], "0x0102");
+        assert_eq!(json["events"][0]["type"], "mined");
+
+        let deserialized: Block = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.hash(), block.hash());
+        assert_eq!(deserialized.events, block.events);
+    }
+}
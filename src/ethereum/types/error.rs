@@ -14,6 +14,7 @@
 
 //! This module implements the Error struct and its methods.
 
+use std::error;
 use std::fmt;
 
 /// An Error represents any error that appears during the interaction with a blockchain.
@@ -36,6 +37,10 @@ pub enum ErrorKind {
     InvalidBlock,
     NodeError,
     ContractError,
+    SignerError,
+    StateError,
+    TransactionError,
+    ReactorError,
 }
 
 impl fmt::Display for Error {
@@ -44,6 +49,10 @@ impl fmt::Display for Error {
             ErrorKind::ContractError => write!(f, "Error on Contract!").unwrap(),
             ErrorKind::InvalidBlock => write!(f, "Not a valid block!").unwrap(),
             ErrorKind::NodeError => write!(f, "Error on blockchain node!").unwrap(),
+            ErrorKind::SignerError => write!(f, "Error while signing!").unwrap(),
+            ErrorKind::StateError => write!(f, "Error while accessing the state!").unwrap(),
+            ErrorKind::TransactionError => write!(f, "Error while sending a transaction!").unwrap(),
+            ErrorKind::ReactorError => write!(f, "Error while reacting on a block!").unwrap(),
         };
 
         write!(f, " Explanation: {}", self.explanation).unwrap();
@@ -51,3 +60,5 @@ impl fmt::Display for Error {
         Ok(())
     }
 }
+
+impl error::Error for Error {}
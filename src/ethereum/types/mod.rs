@@ -16,9 +16,13 @@
 
 pub mod block;
 pub mod error;
+pub mod transaction;
 
 pub use self::block::*;
 pub use self::error::*;
+pub use self::transaction::*;
 use web3::types::H520;
 
+/// A signature in its 65 byte form. It serializes to a `0x` prefixed hex string, like all hashes
+/// of the Ethereum JSON-RPC API.
 pub type Signature = H520;
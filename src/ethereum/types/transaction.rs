@@ -0,0 +1,77 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module covers transactions that are signed locally.
+
+use rlp::RlpStream;
+use web3::types::{Address, Bytes, H256, U256};
+
+/// A raw transaction holds all fields of a transaction that are signed by the sender.
+#[derive(Debug, Clone)]
+pub struct RawTransaction {
+    pub nonce: U256,
+    pub gas_price: U256,
+    pub gas: U256,
+    /// The receiver of the transaction. `None` for contract creations.
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Bytes,
+}
+
+impl RawTransaction {
+    /// RLP encodes the transaction for signing according to EIP-155.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain_id` - The id of the chain that the transaction is signed for.
+    pub fn unsigned_rlp(&self, chain_id: u64) -> Vec<u8> {
+        let mut rlp_stream = RlpStream::new_list(9);
+        self.append_fields(&mut rlp_stream);
+        rlp_stream.append(&chain_id);
+        rlp_stream.append(&0u8);
+        rlp_stream.append(&0u8);
+
+        rlp_stream.out()
+    }
+
+    /// RLP encodes the transaction together with its signature.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - The recovery id of the signature, including the chain id.
+    /// * `r` - The `r` value of the signature.
+    /// * `s` - The `s` value of the signature.
+    pub fn signed_rlp(&self, v: u64, r: H256, s: H256) -> Vec<u8> {
+        let mut rlp_stream = RlpStream::new_list(9);
+        self.append_fields(&mut rlp_stream);
+        rlp_stream.append(&v);
+        rlp_stream.append(&U256::from(r));
+        rlp_stream.append(&U256::from(s));
+
+        rlp_stream.out()
+    }
+
+    /// Appends the six transaction fields that are part of both the unsigned and signed encoding.
+    fn append_fields(&self, rlp_stream: &mut RlpStream) {
+        rlp_stream.append(&self.nonce);
+        rlp_stream.append(&self.gas_price);
+        rlp_stream.append(&self.gas);
+        match self.to {
+            Some(ref to) => rlp_stream.append(to),
+            None => rlp_stream.append_empty_data(),
+        };
+        rlp_stream.append(&self.value);
+        rlp_stream.append(&self.data.0);
+    }
+}
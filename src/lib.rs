@@ -19,26 +19,42 @@
 
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate serde_derive;
 extern crate core;
+extern crate crypto;
+extern crate ethabi;
 extern crate futures;
+extern crate jsonrpc_core;
+extern crate rand;
 extern crate rlp;
 extern crate rpassword;
+extern crate rustc_hex;
+extern crate secp256k1;
+extern crate serde;
+extern crate serde_json;
 extern crate tiny_keccak;
 extern crate tokio_core;
+extern crate toml;
 extern crate web3;
 
-pub use config::Config;
+pub use config::{Chain, Config, ConfigError};
 use ethereum::contract::ContractRegistry;
+use ethereum::gas::GasPolicy;
+use ethereum::retry::RetryPolicy;
 use ethereum::Ethereum;
 use observer::Observer;
+use state::StateStore;
 use std::error::Error;
 use std::sync::Arc;
 
+pub mod commands;
 pub mod config;
 mod ethereum;
 mod observer;
 
 mod reactor;
+mod state;
 
 /// Runs a mosaic node with the given configuration.
 /// Prints all accounts of the origin blockchain to std out.
@@ -49,52 +65,153 @@ mod reactor;
 pub fn run(config: &Config) -> Result<(), Box<Error>> {
     let mut event_loop =
         tokio_core::reactor::Core::new().expect("Could not initialize tokio event loop");
-    let origin = Ethereum::new(
-        config.origin_endpoint(),
-        config.origin_validator_address(),
-        config.origin_polling_interval(),
-        event_loop.handle(),
-    );
-    let auxiliary = Ethereum::new(
-        config.auxiliary_endpoint(),
-        config.auxiliary_validator_address(),
-        config.auxiliary_polling_interval(),
-        event_loop.handle(),
-    );
+    let (origin_observer, auxiliary_observer) = observers(config, &event_loop.handle())?;
+
+    origin_observer.run();
+    auxiliary_observer.run();
+
+    loop {
+        event_loop.turn(None);
+    }
+}
+
+/// Hands a historical range of blocks of one chain to the reactors of that chain and returns
+/// afterwards. The state of the node is not changed.
+///
+/// # Arguments
+///
+/// * `config` - A configuration to run the mosaic node.
+/// * `chain` - The chain whose blocks are replayed.
+/// * `from` - The number of the first block to replay.
+/// * `to` - The number of the last block to replay.
+pub fn replay(config: &Config, chain: Chain, from: u64, to: u64) -> Result<(), Box<Error>> {
+    if from > to {
+        return Err(From::from(format!(
+            "Cannot replay from block {} to lower block {}",
+            from, to
+        )));
+    }
+
+    let mut event_loop =
+        tokio_core::reactor::Core::new().expect("Could not initialize tokio event loop");
+    let (origin_observer, auxiliary_observer) = observers(config, &event_loop.handle())?;
+    let observer = match chain {
+        Chain::Origin => origin_observer,
+        Chain::Auxiliary => auxiliary_observer,
+    };
+
+    event_loop.run(observer.replay(from, to))?;
+    info!("Replayed {} blocks {} to {}", chain, from, to);
+
+    Ok(())
+}
+
+/// Creates the observers of the origin and the auxiliary chain with their reactors.
+/// Reads the password of the auxiliary validator keystore from `stdin`.
+///
+/// # Arguments
+///
+/// * `config` - A configuration to run the mosaic node.
+/// * `event_loop` - The event loop that handles the connections and the reactors.
+///
+/// # Returns
+///
+/// Returns a tuple of the origin and the auxiliary observer.
+fn observers(
+    config: &Config,
+    event_loop: &tokio_core::reactor::Handle,
+) -> Result<(Observer, Observer), Box<Error>> {
+    let (origin, mut auxiliary) = connect(config, event_loop)?;
+    // The reactors of both chains only send transactions to auxiliary, where the block stores and
+    // the polling place are.
+    auxiliary.load_signer(config.auxiliary_keystore_path())?;
 
     let origin = Arc::new(origin);
     let auxiliary = Arc::new(auxiliary);
+    let state = Arc::new(StateStore::open(config.state_path())?);
 
-    // This will panic if construction will fail.
-    let contract_registry =
-        ContractRegistry::new(Arc::clone(&origin), Arc::clone(&auxiliary), config)
-            .expect("Error instantiating contract registry:");
-
-    let origin_reactors = reactor::origin_reactors(
+    let contract_registry = Arc::new(ContractRegistry::new(
         Arc::clone(&origin),
         Arc::clone(&auxiliary),
-        &contract_registry,
         config,
-        event_loop.handle(),
-    ).expect("Error instantiating origin reactors.");
-    ;
+    )?);
 
-    let auxiliary_reactors = reactor::auxiliary_reactors(
+    let (origin_reactors, auxiliary_reactors) = reactor::reactors(
         Arc::clone(&origin),
         Arc::clone(&auxiliary),
         &contract_registry,
         config,
-        event_loop.handle(),
-    ).expect("Error instantiating auxiliary reactors.");
+        Arc::clone(&state),
+    )?;
 
-    let origin_observer = Observer::new(origin, origin_reactors, event_loop.handle());
+    let origin_observer = Observer::new(
+        Chain::Origin,
+        origin,
+        origin_reactors,
+        Arc::clone(&contract_registry),
+        config.origin_confirmations(),
+        Arc::clone(&state),
+        event_loop.clone(),
+    );
 
-    let auxiliary_observer = Observer::new(auxiliary, auxiliary_reactors, event_loop.handle());
+    let auxiliary_observer = Observer::new(
+        Chain::Auxiliary,
+        auxiliary,
+        auxiliary_reactors,
+        contract_registry,
+        config.auxiliary_confirmations(),
+        state,
+        event_loop.clone(),
+    );
 
-    origin_observer.run();
-    auxiliary_observer.run();
+    Ok((origin_observer, auxiliary_observer))
+}
 
-    loop {
-        event_loop.turn(None);
-    }
+/// Creates the connections to the origin and the auxiliary chain.
+/// The connections cannot sign until the keystores of the validators were loaded.
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the chains.
+/// * `event_loop` - The event loop that handles the connections.
+///
+/// # Returns
+///
+/// Returns a tuple of the origin and the auxiliary connection.
+fn connect(
+    config: &Config,
+    event_loop: &tokio_core::reactor::Handle,
+) -> Result<(Ethereum, Ethereum), Box<Error>> {
+    let origin = Ethereum::new(
+        config.origin_endpoints(),
+        config.origin_validator_address(),
+        config.origin_polling_interval(),
+        RetryPolicy {
+            max_retries: config.origin_max_retries() as u32,
+            initial_delay: config.origin_retry_delay(),
+        },
+        GasPolicy {
+            multiplier: config.origin_gas_multiplier(),
+            price_strategy: config.origin_gas_price_strategy(),
+            max_price: config.origin_max_gas_price(),
+        },
+        event_loop.clone(),
+    )?;
+    let auxiliary = Ethereum::new(
+        config.auxiliary_endpoints(),
+        config.auxiliary_validator_address(),
+        config.auxiliary_polling_interval(),
+        RetryPolicy {
+            max_retries: config.auxiliary_max_retries() as u32,
+            initial_delay: config.auxiliary_retry_delay(),
+        },
+        GasPolicy {
+            multiplier: config.auxiliary_gas_multiplier(),
+            price_strategy: config.auxiliary_gas_price_strategy(),
+            max_price: config.auxiliary_max_gas_price(),
+        },
+        event_loop.clone(),
+    )?;
+
+    Ok((origin, auxiliary))
 }
@@ -12,49 +12,214 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[macro_use]
+extern crate clap;
 #[macro_use]
 extern crate log;
 extern crate mosaic;
 extern crate simple_logger;
 
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use log::Level;
-use mosaic::Config;
+use mosaic::{Chain, Config};
 use std::env;
+use std::error::Error;
+use std::path::Path;
 use std::process;
 
 const ERROR_MOSAIC: i32 = 1;
+const ERROR_CONFIG: i32 = 2;
 
 const ENV_LOG_LEVEL: &str = "MOSAIC_LOG_LEVEL";
+const ENV_CONFIG_FILE: &str = "MOSAIC_CONFIG_FILE";
 const DEFAULT_LOG_LEVEL: Level = Level::Info;
 
-/// Reads the configuration and runs the node with it.
+/// Parses the command line, reads the configuration, and runs the given subcommand with it.
+/// Runs the node if no subcommand is given.
+/// The configuration is read from the file given with `--config` or `MOSAIC_CONFIG_FILE` if it
+/// is set and from the environment otherwise.
 fn main() {
-    let log_level = read_log_level();
+    let log_level = match read_log_level() {
+        Ok(log_level) => log_level,
+        Err(error) => {
+            simple_logger::init_with_level(DEFAULT_LOG_LEVEL).unwrap();
+            error!("Invalid configuration:");
+            error!("  {}", error);
+            process::exit(ERROR_CONFIG);
+        }
+    };
     simple_logger::init_with_level(log_level).unwrap();
 
-    let config = Config::new();
+    let matches = app().get_matches();
+
+    // Decoding a header needs neither a configuration nor a node.
+    if let ("decode-header", Some(matches)) = matches.subcommand() {
+        match mosaic::commands::decode_header(matches.value_of("rlp").unwrap()) {
+            Ok(header) => println!("{}", header),
+            Err(e) => {
+                error!("Mosaic error: {}", e);
+                process::exit(ERROR_MOSAIC);
+            }
+        }
+        return;
+    }
+
+    let config = match matches.value_of("config") {
+        Some(path) => Config::from_file(Path::new(path)),
+        None => Config::new(),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(errors) => {
+            error!("Invalid configuration:");
+            for error in errors {
+                error!("  {}", error);
+            }
+            process::exit(ERROR_CONFIG);
+        }
+    };
 
-    if let Err(e) = mosaic::run(&config) {
+    if let Err(e) = run_subcommand(&config, &matches) {
         error!("Mosaic error: {}", e);
         process::exit(ERROR_MOSAIC);
     }
 }
 
+/// Defines the command line interface of the mosaic binary.
+fn app() -> App<'static, 'static> {
+    let chain = Arg::with_name("chain")
+        .help("The chain of the block")
+        .required(true)
+        .possible_values(&["origin", "auxiliary"]);
+    let number = Arg::with_name("number")
+        .help("The number of the block")
+        .required(true);
+
+    App::new("mosaic")
+        .version(crate_version!())
+        .about("Runs a mosaic node")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("FILE")
+                .env(ENV_CONFIG_FILE)
+                .help("Reads the configuration from a TOML file")
+                .takes_value(true),
+        ).subcommand(SubCommand::with_name("run").about("Runs the node (default)"))
+        .subcommand(
+            SubCommand::with_name("check-config")
+                .about("Checks the configuration and reports all problems"),
+        ).subcommand(
+            SubCommand::with_name("status")
+                .about("Prints the heads and latest reported block heights of the block stores"),
+        ).subcommand(
+            SubCommand::with_name("report-block")
+                .about("Reports a single block on its block store")
+                .arg(chain.clone())
+                .arg(number.clone()),
+        ).subcommand(
+            SubCommand::with_name("replay")
+                .about("Hands a range of blocks to the reactors and exits")
+                .arg(
+                    Arg::with_name("chain")
+                        .help("The chain of the blocks")
+                        .required(true)
+                        .possible_values(&["origin", "auxiliary"]),
+                ).arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("NUMBER")
+                        .help("The number of the first block to replay")
+                        .required(true),
+                ).arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .value_name("NUMBER")
+                        .help("The number of the last block to replay")
+                        .required(true),
+                ),
+        ).subcommand(
+            SubCommand::with_name("hash-block")
+                .about("Prints the RLP encoding and the hash of a block")
+                .arg(chain)
+                .arg(number),
+        ).subcommand(
+            SubCommand::with_name("decode-header")
+                .about("Decodes an RLP encoded block header and prints its fields and its hash")
+                .arg(
+                    Arg::with_name("rlp")
+                        .help("The hex encoded RLP of the header")
+                        .required(true),
+                ),
+        )
+}
+
+/// Runs the subcommand that was given on the command line with a valid configuration.
+///
+/// # Arguments
+///
+/// * `config` - The configuration of the mosaic node.
+/// * `matches` - The parsed command line.
+fn run_subcommand(config: &Config, matches: &ArgMatches) -> Result<(), Box<Error>> {
+    match matches.subcommand() {
+        ("check-config", _) => {
+            mosaic::commands::check_config(config)?;
+            println!("Configuration is valid.");
+        }
+        ("status", _) => {
+            for status in mosaic::commands::status(config)? {
+                println!("{}", status);
+            }
+        }
+        ("report-block", Some(matches)) => {
+            let (chain, number) = read_block(matches);
+            match mosaic::commands::report_block(config, chain, number)? {
+                Some(outcome) => println!("Reported block: {}", outcome),
+                None => println!("Block was already reported"),
+            }
+        }
+        ("replay", Some(matches)) => {
+            let chain = value_t!(matches, "chain", Chain).unwrap_or_else(|e| e.exit());
+            let from = value_t!(matches, "from", u64).unwrap_or_else(|e| e.exit());
+            let to = value_t!(matches, "to", u64).unwrap_or_else(|e| e.exit());
+            mosaic::replay(config, chain, from, to)?;
+        }
+        ("hash-block", Some(matches)) => {
+            let (chain, number) = read_block(matches);
+            println!("{}", mosaic::commands::hash_block(config, chain, number)?);
+        }
+        _ => mosaic::run(config)?,
+    };
+
+    Ok(())
+}
+
+/// Reads the chain and the number of a block from the arguments of a subcommand.
+/// Exits with a usage message if the number is not valid.
+fn read_block(matches: &ArgMatches) -> (Chain, u64) {
+    let chain = value_t!(matches, "chain", Chain).unwrap_or_else(|e| e.exit());
+    let number = value_t!(matches, "number", u64).unwrap_or_else(|e| e.exit());
+
+    (chain, number)
+}
+
 /// Reads the log level from the environment. If it is not set it falls back to
 /// the default log level.
-/// It panics if a log level should be set that is not known.
-fn read_log_level() -> Level {
+/// Returns an error if a log level should be set that is not known.
+fn read_log_level() -> Result<Level, String> {
     match env::var(ENV_LOG_LEVEL) {
         Ok(level) => match level.as_ref() {
-            "TRACE" => Level::Trace,
-            "DEBUG" => Level::Debug,
-            "INFO" => Level::Info,
-            "WARN" => Level::Warn,
-            _ => panic!(
-                "Unknown log level set. Allowed are: TRACE, DEBUG, INFO, WARN. Found: {}",
-                level
-            ),
+            "TRACE" => Ok(Level::Trace),
+            "DEBUG" => Ok(Level::Debug),
+            "INFO" => Ok(Level::Info),
+            "WARN" => Ok(Level::Warn),
+            _ => Err(format!(
+                "Unknown log level '{}' of {}. Allowed are: TRACE, DEBUG, INFO, WARN",
+                level, ENV_LOG_LEVEL
+            )),
         },
-        Err(_) => DEFAULT_LOG_LEVEL,
+        Err(_) => Ok(DEFAULT_LOG_LEVEL),
     }
 }
@@ -0,0 +1,270 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module tracks the recent canonical chain of a blockchain to detect chain reorganisations.
+//! Blocks are only confirmed once enough blocks were built on top of them. Reorganisations of
+//! unconfirmed blocks are not reported, as these blocks were never handed to the reactors.
+
+use std::collections::VecDeque;
+use web3::types::H256;
+
+use ethereum::types::Block;
+
+/// A reorg describes a chain reorganisation. The dropped blocks had been confirmed, but are no
+/// longer part of the canonical chain. They were replaced by the added blocks.
+#[derive(Debug, Clone)]
+pub struct Reorg {
+    /// The confirmed blocks that were removed from the canonical chain, in ascending order.
+    pub dropped: Vec<Block>,
+    /// The newly confirmed blocks of the new canonical branch, in ascending order.
+    pub added: Vec<Block>,
+}
+
+/// An update of the tracked chain after new blocks were added.
+#[derive(Debug)]
+pub struct ChainUpdate {
+    /// A reorg if confirmed blocks were dropped from the canonical chain.
+    pub reorg: Option<Reorg>,
+    /// The blocks that became confirmed, in ascending order.
+    pub confirmed: Vec<Block>,
+}
+
+/// The chain tracker keeps the most recent blocks of the canonical chain, linked by their parent
+/// hashes.
+pub struct ChainTracker {
+    /// The tracked blocks in ascending order. The last block is the tip of the chain.
+    blocks: VecDeque<Block>,
+    /// The number of tracked blocks, counted from the oldest one, that are confirmed.
+    confirmed: usize,
+    /// The maximum number of blocks that are tracked. Reorgs deeper than this cannot be detected
+    /// completely.
+    max_depth: usize,
+    /// The number of blocks that must follow a block before it is confirmed.
+    confirmations: u64,
+}
+
+impl ChainTracker {
+    /// Creates a new, empty chain tracker.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_reorg_depth` - The maximum depth of a reorg of confirmed blocks that can be detected.
+    /// * `confirmations` - The number of blocks that must follow a block before it is confirmed.
+    pub fn new(max_reorg_depth: usize, confirmations: u64) -> Self {
+        // Unconfirmed blocks must never fall out of the tracked chain.
+        let max_depth = max_reorg_depth + confirmations as usize + 1;
+
+        ChainTracker {
+            blocks: VecDeque::with_capacity(max_depth),
+            confirmed: 0,
+            max_depth,
+            confirmations,
+        }
+    }
+
+    /// Returns true if the block with the given hash is part of the tracked canonical chain.
+    pub fn contains(&self, hash: &H256) -> bool {
+        self.position(hash).is_some()
+    }
+
+    /// Returns the hash of the parent block that must be retrieved to connect the given branch to
+    /// the tracked chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - A branch of blocks in ascending order.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the branch connects to the tracked chain or if it cannot be connected
+    /// within the tracked depth.
+    pub fn missing_parent(&self, branch: &[Block]) -> Option<H256> {
+        let first = branch.first()?;
+        let oldest = self.blocks.front()?;
+
+        if self.contains(&first.parent_hash)
+            || first.number <= oldest.number
+            || branch.len() >= self.max_depth
+        {
+            None
+        } else {
+            Some(first.parent_hash)
+        }
+    }
+
+    /// Adds a branch to the tracked chain. The branch becomes the new tip of the canonical chain.
+    /// All tracked blocks that are not ancestors of the branch are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - A branch of blocks in ascending order.
+    ///
+    /// # Returns
+    ///
+    /// Returns the blocks that became confirmed and a `Reorg` if confirmed blocks were dropped from
+    /// the canonical chain.
+    pub fn extend(&mut self, branch: Vec<Block>) -> ChainUpdate {
+        let first_number = match branch.first() {
+            Some(first) => first.number,
+            None => {
+                return ChainUpdate {
+                    reorg: None,
+                    confirmed: vec![],
+                }
+            }
+        };
+
+        let fork_point = match branch
+            .first()
+            .and_then(|first| self.position(&first.parent_hash))
+        {
+            Some(parent) => parent + 1,
+            // The branch does not connect to the tracked chain. All tracked blocks that are not
+            // lower than the branch are replaced.
+            None => self
+                .blocks
+                .iter()
+                .position(|block| block.number >= first_number)
+                .unwrap_or_else(|| self.blocks.len()),
+        };
+
+        // Dropped blocks that were not confirmed yet are replaced silently.
+        let dropped: Vec<Block> = self
+            .blocks
+            .drain(fork_point..)
+            .take(self.confirmed.saturating_sub(fork_point))
+            .collect();
+        self.confirmed = self.confirmed.min(fork_point);
+        self.blocks.extend(branch);
+
+        let confirmed = self.confirm();
+        while self.blocks.len() > self.max_depth {
+            self.blocks.pop_front();
+            self.confirmed = self.confirmed.saturating_sub(1);
+        }
+
+        ChainUpdate {
+            reorg: if dropped.is_empty() {
+                None
+            } else {
+                Some(Reorg {
+                    dropped,
+                    added: confirmed.clone(),
+                })
+            },
+            confirmed,
+        }
+    }
+
+    /// Marks all blocks that have enough blocks on top of them as confirmed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the newly confirmed blocks in ascending order.
+    fn confirm(&mut self) -> Vec<Block> {
+        let tip_number = match self.blocks.back() {
+            Some(tip) => tip.number.low_u64(),
+            None => return vec![],
+        };
+
+        let newly_confirmed: Vec<Block> = self
+            .blocks
+            .iter()
+            .skip(self.confirmed)
+            .take_while(|block| block.number.low_u64() + self.confirmations <= tip_number)
+            .cloned()
+            .collect();
+        self.confirmed += newly_confirmed.len();
+
+        newly_confirmed
+    }
+
+    /// Returns the position of the block with the given hash in the tracked chain.
+    fn position(&self, hash: &H256) -> Option<usize> {
+        self.blocks.iter().position(|block| &block.hash == hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ethereum::types::block::fixtures;
+
+    fn block(number: u64, hash: u64, parent_hash: u64) -> Block {
+        Block {
+            hash: H256::from(hash),
+            parent_hash: H256::from(parent_hash),
+            ..fixtures::block(number)
+        }
+    }
+
+    #[test]
+    fn the_chain_tracker_detects_reorgs() {
+        let mut tracker = ChainTracker::new(2, 0);
+        assert_eq!(tracker.extend(vec![block(1, 11, 10)]).confirmed.len(), 1);
+        assert!(tracker.extend(vec![block(2, 12, 11)]).reorg.is_none());
+        assert!(tracker.extend(vec![block(3, 13, 12)]).reorg.is_none());
+        assert!(tracker.extend(vec![block(4, 14, 13)]).reorg.is_none());
+        assert!(!tracker.contains(&H256::from(11)));
+
+        // A competing block 4 whose parent is known.
+        let branch = vec![block(4, 24, 13)];
+        assert_eq!(tracker.missing_parent(&branch), None);
+        let reorg = tracker.extend(branch).reorg.unwrap();
+        assert_eq!(reorg.dropped[0].hash, H256::from(14));
+        assert_eq!(reorg.added[0].hash, H256::from(24));
+
+        // A block 5 whose parent 4 is not known yet.
+        let branch = vec![block(5, 35, 34)];
+        assert_eq!(tracker.missing_parent(&branch), Some(H256::from(34)));
+        let branch = vec![block(4, 34, 13), block(5, 35, 34)];
+        assert_eq!(tracker.missing_parent(&branch), None);
+        let reorg = tracker.extend(branch).reorg.unwrap();
+        assert_eq!(reorg.dropped.len(), 1);
+        assert_eq!(reorg.added.len(), 2);
+        assert!(tracker.contains(&H256::from(35)));
+        assert!(!tracker.contains(&H256::from(24)));
+    }
+
+    #[test]
+    fn the_chain_tracker_confirms_blocks() {
+        let mut tracker = ChainTracker::new(2, 2);
+        assert!(tracker.extend(vec![block(1, 11, 10)]).confirmed.is_empty());
+        assert!(tracker.extend(vec![block(2, 12, 11)]).confirmed.is_empty());
+
+        let update = tracker.extend(vec![block(3, 13, 12)]);
+        assert_eq!(update.confirmed.len(), 1);
+        assert_eq!(update.confirmed[0].hash, H256::from(11));
+
+        // Unconfirmed blocks are replaced silently.
+        let update = tracker.extend(vec![block(3, 23, 12), block(4, 24, 23)]);
+        assert!(update.reorg.is_none());
+        assert_eq!(update.confirmed.len(), 1);
+        assert_eq!(update.confirmed[0].hash, H256::from(12));
+
+        // Replacing confirmed blocks is a reorg.
+        let update = tracker.extend(vec![
+            block(2, 32, 11),
+            block(3, 33, 32),
+            block(4, 34, 33),
+            block(5, 35, 34),
+        ]);
+        let reorg = update.reorg.unwrap();
+        assert_eq!(reorg.dropped.len(), 1);
+        assert_eq!(reorg.dropped[0].hash, H256::from(12));
+        assert_eq!(reorg.added.len(), 2);
+        assert_eq!(update.confirmed[1].hash, H256::from(33));
+    }
+}
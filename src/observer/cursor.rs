@@ -0,0 +1,141 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module tracks the last processed block of a chain while the reactors react on several
+//! blocks at the same time. The reactions may finish in any order, but the last processed block
+//! only advances in the order in which the blocks were handed to the reactors. It stops at the
+//! first block on which a reaction failed, so that the block is processed again after a restart.
+
+use std::collections::VecDeque;
+
+/// A ticket identifies a block that was handed to the reactors.
+pub type Ticket = u64;
+
+/// A reaction on a block that was handed to the reactors.
+struct Reaction {
+    ticket: Ticket,
+    number: u64,
+    /// Whether the reaction succeeded, or `None` while it is running.
+    succeeded: Option<bool>,
+}
+
+/// The cursor points to the last block that all reactions up to and including it succeeded on.
+#[derive(Default)]
+pub struct Cursor {
+    /// The reactions in the order in which the blocks were handed to the reactors. Reactions are
+    /// removed once the cursor advanced over them.
+    reactions: VecDeque<Reaction>,
+    next_ticket: Ticket,
+    /// Set once a reaction failed. The cursor never advances over the failed block.
+    stalled: bool,
+}
+
+impl Cursor {
+    /// Creates a new cursor without any reactions.
+    pub fn new() -> Self {
+        Cursor::default()
+    }
+
+    /// Records that a block was handed to the reactors. Must be called in the order in which the
+    /// blocks are handed to the reactors.
+    ///
+    /// # Arguments
+    ///
+    /// * `number` - The number of the block.
+    ///
+    /// # Returns
+    ///
+    /// Returns the ticket that identifies the reaction when it finished.
+    pub fn start(&mut self, number: u64) -> Ticket {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+
+        // Reactions behind a failed one can never move the cursor.
+        if !self.stalled {
+            self.reactions.push_back(Reaction {
+                ticket,
+                number,
+                succeeded: None,
+            });
+        }
+
+        ticket
+    }
+
+    /// Records that the reaction with the given ticket finished.
+    ///
+    /// # Arguments
+    ///
+    /// * `ticket` - The ticket of the reaction.
+    /// * `succeeded` - Whether all reactors succeeded on the block.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of the new last processed block if the cursor advanced.
+    pub fn finish(&mut self, ticket: Ticket, succeeded: bool) -> Option<u64> {
+        let position = self
+            .reactions
+            .iter()
+            .position(|reaction| reaction.ticket == ticket)?;
+        self.reactions[position].succeeded = Some(succeeded);
+        if !succeeded {
+            self.stalled = true;
+            self.reactions.truncate(position + 1);
+        }
+
+        let mut last_processed_block = None;
+        while let Some(Some(true)) = self.reactions.front().map(|reaction| reaction.succeeded) {
+            last_processed_block = self.reactions.pop_front().map(|reaction| reaction.number);
+        }
+
+        last_processed_block
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn the_cursor_advances_in_order() {
+        let mut cursor = Cursor::new();
+        let first = cursor.start(1);
+        let second = cursor.start(2);
+        let third = cursor.start(3);
+
+        // The cursor waits for the first block.
+        assert_eq!(cursor.finish(third, true), None);
+        assert_eq!(cursor.finish(second, true), None);
+        assert_eq!(cursor.finish(first, true), Some(3));
+
+        let fourth = cursor.start(4);
+        assert_eq!(cursor.finish(fourth, true), Some(4));
+    }
+
+    #[test]
+    fn the_cursor_stops_before_a_failed_block() {
+        let mut cursor = Cursor::new();
+        let first = cursor.start(1);
+        let second = cursor.start(2);
+        let third = cursor.start(3);
+
+        assert_eq!(cursor.finish(second, false), None);
+        assert_eq!(cursor.finish(third, true), None);
+        // Blocks before the failed one still move the cursor.
+        assert_eq!(cursor.finish(first, true), Some(1));
+
+        let fourth = cursor.start(4);
+        assert_eq!(cursor.finish(fourth, true), None);
+    }
+}
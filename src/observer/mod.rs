@@ -14,17 +14,62 @@
 
 //! This module is about observing blockchains.
 
+use futures::future::{self, Either, Loop};
 use futures::prelude::*;
-use std::sync::Arc;
+use futures::stream;
+use futures::sync::oneshot;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
+use web3::types::{BlockId, BlockNumber};
 
+use config::Chain;
+use ethereum::contract::ContractRegistry;
+use ethereum::filter::EventFilter;
+use ethereum::types::{Block, Error, ErrorKind};
 use ethereum::Ethereum;
-use reactor::React;
+use observer::chain::ChainTracker;
+use observer::cursor::Cursor;
+use reactor::{React, ReactorOutcome};
+use state::StateStore;
+
+pub mod chain;
+pub mod cursor;
+
+/// The maximum depth of a chain reorganisation of confirmed blocks that can be detected.
+const MAX_REORG_DEPTH: usize = 128;
+/// The number of seconds that a reactor may take to react on a block. Reactions that take longer
+/// fail. Reactions may wait for transactions to be mined, including re-submissions.
+const REACTION_TIMEOUT_SECONDS: u64 = 3600;
+/// The maximum number of confirmed blocks that the reactors react on at the same time.
+const MAX_PARALLEL_REACTIONS: usize = 16;
+/// The number of seconds to wait before a failed request of the backfill is retried.
+const BACKFILL_RETRY_SECONDS: u64 = 5;
+
+/// A reactor together with the filter of the events that it subscribed to.
+struct Subscriber {
+    reactor: Box<React>,
+    event_filter: EventFilter,
+}
 
 /// This represents as observer of a block chain.
+#[derive(Clone)]
 pub struct Observer {
+    /// The chain that is observed.
+    chain: Chain,
     block_chain: Arc<Ethereum>,
     /// List of block reactors. These are notified when any new block is generated.
-    reactors: Arc<Vec<Box<React>>>,
+    subscribers: Arc<Vec<Subscriber>>,
+    /// The events that any reactor subscribed to. Only these events are retrieved from the node.
+    event_filter: EventFilter,
+    /// Decodes the events of the blocks for the reactors.
+    contract_registry: Arc<ContractRegistry>,
+    /// The recent canonical chain, used to detect chain reorganisations.
+    chain_tracker: Arc<Mutex<ChainTracker>>,
+    /// The state store persists the last processed block of the chain.
+    state: Arc<StateStore>,
+    /// Tracks the last processed block while the reactors react on several blocks at once.
+    cursor: Arc<Mutex<Cursor>>,
     event_loop: tokio_core::reactor::Handle,
 }
 
@@ -33,25 +78,58 @@ impl Observer {
     ///
     /// # Arguments
     ///
-    /// * `origin` - A blockchain object that points to origin.
-    /// * `auxiliary` - A blockchain object that points to auxiliary.
+    /// * `chain` - The chain that is observed.
+    /// * `block_chain` - A blockchain object that points to the observed chain.
+    /// * `reactors` - The reactors that react on the blocks of the chain.
+    /// * `contract_registry` - The contracts whose events are decoded for the reactors.
+    /// * `confirmations` - The number of blocks that must follow a block before it is handed to
+    /// the reactors.
+    /// * `state` - The state store that persists the last processed block.
     /// * `event_loop` - The reactor's event loop to handle the tasks spawned by this observer.
     pub fn new(
+        chain: Chain,
         block_chain: Arc<Ethereum>,
         reactors: Vec<Box<React>>,
+        contract_registry: Arc<ContractRegistry>,
+        confirmations: u64,
+        state: Arc<StateStore>,
         event_loop: tokio_core::reactor::Handle,
     ) -> Self {
-        let reactors = Arc::new(reactors);
+        let subscribers: Vec<Subscriber> = reactors
+            .into_iter()
+            .map(|reactor| Subscriber {
+                event_filter: EventFilter::new(reactor.subscriptions()),
+                reactor,
+            }).collect();
+        let event_filter = EventFilter::new(
+            subscribers
+                .iter()
+                .flat_map(|subscriber| subscriber.reactor.subscriptions()),
+        );
 
         Observer {
+            chain,
             block_chain,
-            reactors,
+            subscribers: Arc::new(subscribers),
+            event_filter,
+            contract_registry,
+            chain_tracker: Arc::new(Mutex::new(ChainTracker::new(
+                MAX_REORG_DEPTH,
+                confirmations,
+            ))),
+            state,
+            cursor: Arc::new(Mutex::new(Cursor::new())),
             event_loop,
         }
     }
+
     /// Runs a mosaic observer. The observer observes blocks from a block chain. When a new block
     /// is observed, the observer hands new  tasks to the reactor,
     ///
+    /// The observer first backfills all blocks that were added to the chain since the last
+    /// processed block of a previous run. Afterwards, it streams new blocks from the chain.
+    /// The reactors react on up to `MAX_PARALLEL_REACTIONS` confirmed blocks at the same time.
+    ///
     /// Observations are handled as streams that are added to the given event loop.
     ///
     pub fn run(&self) {
@@ -61,27 +139,535 @@ impl Observer {
         // block as we caught all blocks and errors and mapped both to `Option`al blocks (`None` in the
         // error case).
         let worker = self
-            .block_chain
-            .stream_blocks()
+            .backfill()
+            .chain(self.block_chain.stream_blocks(self.event_filter.clone()))
+            .and_then(verify)
             .then(|item| match item {
                 Ok(block) => Ok(Some(block)),
                 Err(error) => {
                     error!("Error when streaming from chain: {}", error);
                     Ok(None)
                 }
-            }).for_each({
-                let reactors = Arc::clone(&self.reactors);
-                move |block| {
-                    let block = match block {
-                        Some(block) => block,
-                        None => return Ok(()),
-                    };
-
-                    reactors.iter().for_each(|reactor| reactor.react(&block));
-                    Ok(())
+            }).and_then({
+                let observer = self.clone();
+                move |block| match block {
+                    Some(block) => Either::A(observer.confirm(block)),
+                    None => Either::B(future::ok(vec![])),
                 }
-            });
+            }).map(stream::iter_ok)
+            .flatten()
+            .map({
+                let observer = self.clone();
+                move |block| observer.process(block)
+            }).buffer_unordered(MAX_PARALLEL_REACTIONS)
+            .for_each(|()| Ok(()));
 
         self.event_loop.spawn(worker);
     }
+
+    /// Replays a range of blocks. Every block in the range is handed to the reactors in ascending
+    /// order, without waiting for confirmations and without updating the last processed block.
+    /// Fails on a block whose header does not hash to its hash or on which a reactor fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The number of the first block to replay.
+    /// * `to` - The number of the last block to replay.
+    ///
+    /// # Returns
+    ///
+    /// Returns a future that resolves after the reactors finished with the last block or fails on
+    /// the first block that cannot be retrieved or processed.
+    pub fn replay(&self, from: u64, to: u64) -> impl Future<Item = (), Error = Error> {
+        let block_chain = Arc::clone(&self.block_chain);
+        let event_filter = self.event_filter.clone();
+        let subscribers = Arc::clone(&self.subscribers);
+        let contract_registry = Arc::clone(&self.contract_registry);
+        let event_loop = self.event_loop.clone();
+
+        stream::iter_ok(from..to + 1)
+            .and_then(move |number| {
+                block_chain
+                    .block(BlockId::Number(BlockNumber::Number(number)), &event_filter)
+                    .and_then(verify)
+            }).for_each(move |block| {
+                info!("Replaying block {}", block.number);
+                react(
+                    &subscribers,
+                    &contract_registry,
+                    &block,
+                    Duration::from_secs(REACTION_TIMEOUT_SECONDS),
+                    &event_loop,
+                )
+            })
+    }
+
+    /// Returns a stream of all blocks after the last processed block up to the current head of
+    /// the chain. The stream is empty if no block was processed before.
+    /// Failed requests are retried until they succeed. Otherwise, the stream of new blocks would
+    /// start after a gap and the last processed block would move past the blocks in the gap.
+    fn backfill(&self) -> impl Stream<Item = Block, Error = Error> {
+        let chain = self.chain;
+        let last_processed_block = self.state.chain(chain).last_processed_block;
+        let block_chain = Arc::clone(&self.block_chain);
+        let event_filter = self.event_filter.clone();
+        let event_loop = self.event_loop.clone();
+        let retry_delay = Duration::from_secs(BACKFILL_RETRY_SECONDS);
+
+        until_success(
+            format!("Retrieving the {} head to backfill", chain),
+            retry_delay,
+            {
+                let block_chain = Arc::clone(&self.block_chain);
+                move || block_chain.block_number()
+            },
+            &self.event_loop,
+        ).map(move |head| {
+            let from = match last_processed_block {
+                Some(last_processed_block) => last_processed_block + 1,
+                None => head + 1,
+            };
+            if from <= head {
+                info!("Backfilling {} blocks {} to {}", chain, from, head);
+            }
+
+            stream::iter_ok(from..head + 1).and_then(move |number| {
+                let block_chain = Arc::clone(&block_chain);
+                let event_filter = event_filter.clone();
+                until_success(
+                    format!("Backfilling {} block {}", chain, number),
+                    retry_delay,
+                    move || {
+                        block_chain
+                            .block(BlockId::Number(BlockNumber::Number(number)), &event_filter)
+                            .and_then(verify)
+                    },
+                    &event_loop,
+                )
+            })
+        }).flatten_stream()
+    }
+
+    /// Confirms a verified block of the chain.
+    ///
+    /// The observer follows the canonical chain by the parent hashes of the blocks. Missing
+    /// ancestors of a block are retrieved from the node. If confirmed blocks were dropped from the
+    /// canonical chain, the reactors are notified about the reorg before the new canonical branch
+    /// is handed to them.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The observed block.
+    ///
+    /// # Returns
+    ///
+    /// Returns a future of the blocks that became confirmed, in ascending order.
+    fn confirm(&self, block: Block) -> impl Future<Item = Vec<Block>, Error = ()> {
+        if self.chain_tracker.lock().unwrap().contains(&block.hash) {
+            return Either::A(future::ok(vec![]));
+        }
+
+        // Walking back from the new block until it connects to the tracked chain.
+        let branch = future::loop_fn(vec![block], {
+            let block_chain = Arc::clone(&self.block_chain);
+            let event_filter = self.event_filter.clone();
+            let chain_tracker = Arc::clone(&self.chain_tracker);
+            move |mut branch| {
+                let missing_parent = chain_tracker.lock().unwrap().missing_parent(&branch);
+                match missing_parent {
+                    Some(parent_hash) => Either::A(
+                        block_chain
+                            .block(BlockId::from(parent_hash), &event_filter)
+                            .and_then(verify)
+                            .map(move |parent| {
+                                branch.insert(0, parent);
+                                Loop::Continue(branch)
+                            }),
+                    ),
+                    None => Either::B(future::ok(Loop::Break(branch))),
+                }
+            }
+        });
+
+        let chain = self.chain;
+        let subscribers = Arc::clone(&self.subscribers);
+        let chain_tracker = Arc::clone(&self.chain_tracker);
+        Either::B(branch.then(move |branch| {
+            let branch = match branch {
+                Ok(branch) => branch,
+                Err(error) => {
+                    error!("Error when retrieving ancestors of block: {}", error);
+                    return Ok(vec![]);
+                }
+            };
+
+            let update = chain_tracker.lock().unwrap().extend(branch);
+            if let Some(reorg) = update.reorg {
+                warn!(
+                    "Chain reorganisation on {}: dropped {} block(s), added {} block(s)",
+                    chain,
+                    reorg.dropped.len(),
+                    reorg.added.len()
+                );
+                subscribers
+                    .iter()
+                    .for_each(|subscriber| subscriber.reactor.react_to_reorg(&reorg));
+            }
+
+            Ok(update.confirmed)
+        }))
+    }
+
+    /// Processes a confirmed block of the chain. The reactions start right away, so blocks must
+    /// be handed to this function in ascending order. The last processed block is updated in the
+    /// same order once all reactors succeeded on all blocks up to it, even if the reactions on
+    /// later blocks finish first.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The confirmed block.
+    fn process(&self, block: Block) -> impl Future<Item = (), Error = ()> {
+        let chain = self.chain;
+        let number = block.number.low_u64();
+        let ticket = self.cursor.lock().unwrap().start(number);
+        let cursor = Arc::clone(&self.cursor);
+        let state = Arc::clone(&self.state);
+
+        react(
+            &self.subscribers,
+            &self.contract_registry,
+            &block,
+            Duration::from_secs(REACTION_TIMEOUT_SECONDS),
+            &self.event_loop,
+        ).then(move |result| {
+            if let Err(error) = &result {
+                error!(
+                    "Processing {} block {} failed, it will be processed again after a restart: {}",
+                    chain, number, error
+                );
+            }
+
+            let last_processed_block = cursor.lock().unwrap().finish(ticket, result.is_ok());
+            if let Some(last_processed_block) = last_processed_block {
+                if let Err(error) = state.update(chain, |state| {
+                    state.last_processed_block = Some(last_processed_block)
+                }) {
+                    error!("Error when storing last processed block: {}", error);
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Hands a block to all reactors and waits until all of them finished.
+/// Every reactor gets the block with the events that it subscribed to and their decoded form.
+/// The reactions run as tasks of the event loop. A reaction that times out keeps running, so that
+/// the transactions that it sent are still tracked.
+///
+/// # Arguments
+///
+/// * `subscribers` - The reactors that react on the block.
+/// * `contract_registry` - The contracts whose events are decoded.
+/// * `block` - The block to react on.
+/// * `timeout` - The time that a reactor may take to react on the block.
+/// * `event_loop` - The event loop that runs and times out the reactions.
+///
+/// # Returns
+///
+/// Returns a future that fails if any reactor fails or does not finish within the timeout. The
+/// error lists the errors of all failed reactors.
+fn react(
+    subscribers: &[Subscriber],
+    contract_registry: &ContractRegistry,
+    block: &Block,
+    timeout: Duration,
+    event_loop: &Handle,
+) -> impl Future<Item = (), Error = Error> {
+    let number = block.number.low_u64();
+    let reactions: Vec<_> = subscribers
+        .iter()
+        .map(|subscriber| {
+            let mut block = block.clone();
+            block
+                .events
+                .retain(|event| subscriber.event_filter.matches(event));
+            let events = contract_registry.decode_events(&block);
+
+            let (sender, receiver) = oneshot::channel();
+            event_loop.spawn(subscriber.reactor.react(&block, &events).then(|result| {
+                // The receiver is gone if the reaction timed out.
+                let _ = sender.send(result);
+                Ok(())
+            }));
+            let reaction = receiver.then(|result| match result {
+                Ok(result) => result,
+                Err(_) => Err(Error::new(
+                    ErrorKind::ReactorError,
+                    "Reaction was canceled".to_string(),
+                )),
+            });
+
+            let timeout = future::result(Timeout::new(timeout, event_loop))
+                .flatten()
+                .then(move |result| -> Result<ReactorOutcome, Error> {
+                    Err(Error::new(
+                        ErrorKind::ReactorError,
+                        match result {
+                            Ok(()) => format!(
+                                "Reaction did not finish within {} seconds",
+                                timeout.as_secs()
+                            ),
+                            Err(error) => format!("Could not time out reaction: {}", error),
+                        },
+                    ))
+                });
+
+            reaction
+                .select(timeout)
+                .map(|(outcome, _)| outcome)
+                .map_err(|(error, _)| error)
+                .then(Ok::<_, Error>)
+        }).collect();
+
+    future::join_all(reactions).and_then(move |results| aggregate(number, results))
+}
+
+/// Runs a request until it succeeds.
+///
+/// # Arguments
+///
+/// * `description` - Describes the request in the log when an attempt failed.
+/// * `delay` - The time to wait after a failed attempt.
+/// * `request` - Starts a new attempt of the request.
+/// * `event_loop` - The event loop that times the attempts.
+///
+/// # Returns
+///
+/// Returns a future of the result of the first successful attempt. It only fails if an attempt
+/// cannot be delayed.
+fn until_success<F, R>(
+    description: String,
+    delay: Duration,
+    request: F,
+    event_loop: &Handle,
+) -> impl Future<Item = R::Item, Error = Error>
+where
+    F: Fn() -> R,
+    R: Future<Error = Error>,
+{
+    let event_loop = event_loop.clone();
+    future::loop_fn((), move |()| {
+        let description = description.clone();
+        let event_loop = event_loop.clone();
+        request().then(move |result| match result {
+            Ok(item) => Either::A(future::ok(Loop::Break(item))),
+            Err(error) => {
+                warn!("{} failed, retrying: {}", description, error);
+                Either::B(
+                    future::result(Timeout::new(delay, &event_loop))
+                        .flatten()
+                        .map(|()| Loop::Continue(()))
+                        .map_err(move |error| {
+                            Error::new(
+                                ErrorKind::NodeError,
+                                format!(
+                                    "{} failed, could not delay the retry: {}",
+                                    description, error
+                                ),
+                            )
+                        }),
+                )
+            }
+        })
+    })
+}
+
+/// Combines the results of all reactors on a block.
+///
+/// # Arguments
+///
+/// * `number` - The number of the block.
+/// * `results` - The results of the reactions of all reactors.
+///
+/// # Returns
+///
+/// Returns an error that lists the errors of all failed reactors if any reactor failed.
+fn aggregate(number: u64, results: Vec<Result<ReactorOutcome, Error>>) -> Result<(), Error> {
+    let errors: Vec<String> = results
+        .into_iter()
+        .filter_map(|result| match result {
+            Ok(outcome) => {
+                debug!("Reaction on block {}: {}", number, outcome);
+                None
+            }
+            Err(error) => Some(error.to_string()),
+        }).collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::ReactorError,
+            format!(
+                "{} reactor(s) failed on block {}: {}",
+                errors.len(),
+                number,
+                errors.join(" ")
+            ),
+        ))
+    }
+}
+
+/// Verifies that the header of a block hashes to the hash that the node reported for the block.
+/// A faulty or compromised node must not be able to make the reactors act on a forged header.
+///
+/// # Arguments
+///
+/// * `block` - The block as the node returned it.
+///
+/// # Returns
+///
+/// Returns the block or an `InvalidBlock` error if the hashes differ.
+fn verify(block: Block) -> Result<Block, Error> {
+    match block.verify_hash() {
+        Ok(()) => Ok(block),
+        Err(error) => {
+            error!("Rejecting block {:x} from node: {}", block.hash, error);
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use tokio_core::reactor::Core;
+
+    use ethereum::contract::ContractEvent;
+    use ethereum::types::block::fixtures::block;
+    use reactor::Reaction;
+
+    /// A reactor whose reactions are built by the given function.
+    struct Reactor<F: Fn() -> Reaction>(F);
+
+    impl<F: Fn() -> Reaction> React for Reactor<F> {
+        fn react(&self, _block: &Block, _events: &[ContractEvent]) -> Reaction {
+            (self.0)()
+        }
+    }
+
+    fn subscriber<F: Fn() -> Reaction + 'static>(reaction: F) -> Subscriber {
+        Subscriber {
+            reactor: Box::new(Reactor(reaction)),
+            event_filter: EventFilter::default(),
+        }
+    }
+
+    #[test]
+    fn failed_requests_are_retried_until_they_succeed() {
+        let mut event_loop = Core::new().unwrap();
+        let attempts = Rc::new(Cell::new(0));
+
+        let result = event_loop.run(until_success(
+            "Test request".to_string(),
+            Duration::from_millis(1),
+            {
+                let attempts = Rc::clone(&attempts);
+                move || {
+                    attempts.set(attempts.get() + 1);
+                    if attempts.get() < 3 {
+                        future::err(Error::new(ErrorKind::NodeError, "Unavailable".to_string()))
+                    } else {
+                        future::ok(attempts.get())
+                    }
+                }
+            },
+            &event_loop.handle(),
+        ));
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn the_errors_of_all_reactors_are_aggregated() {
+        let mut event_loop = Core::new().unwrap();
+        let subscribers = vec![
+            subscriber(|| Box::new(future::ok(ReactorOutcome::Skipped))),
+            subscriber(|| {
+                Box::new(future::err(Error::new(
+                    ErrorKind::ReactorError,
+                    "first".to_string(),
+                )))
+            }),
+            subscriber(|| {
+                Box::new(future::err(Error::new(
+                    ErrorKind::ReactorError,
+                    "second".to_string(),
+                )))
+            }),
+        ];
+
+        let error = event_loop
+            .run(react(
+                &subscribers,
+                &ContractRegistry::default(),
+                &block(7),
+                Duration::from_secs(60),
+                &event_loop.handle(),
+            )).unwrap_err()
+            .to_string();
+        assert!(error.contains("2 reactor(s) failed on block 7"));
+        assert!(error.contains("first"));
+        assert!(error.contains("second"));
+
+        let result = event_loop.run(react(
+            &subscribers[0..1],
+            &ContractRegistry::default(),
+            &block(8),
+            Duration::from_secs(60),
+            &event_loop.handle(),
+        ));
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn reactions_keep_running_after_they_timed_out() {
+        let mut event_loop = Core::new().unwrap();
+        let handle = event_loop.handle();
+        let finished = Rc::new(Cell::new(false));
+        let subscribers = vec![subscriber({
+            let handle = handle.clone();
+            let finished = Rc::clone(&finished);
+            move || {
+                let finished = Rc::clone(&finished);
+                Box::new(
+                    Timeout::new(Duration::from_millis(50), &handle)
+                        .unwrap()
+                        .then(move |_| {
+                            finished.set(true);
+                            Ok(ReactorOutcome::Acted("sent".to_string()))
+                        }),
+                )
+            }
+        })];
+
+        let error = event_loop
+            .run(react(
+                &subscribers,
+                &ContractRegistry::default(),
+                &block(7),
+                Duration::from_millis(10),
+                &handle,
+            )).unwrap_err();
+        assert!(error.to_string().contains("did not finish"));
+        assert!(!finished.get());
+
+        event_loop
+            .run(Timeout::new(Duration::from_millis(100), &handle).unwrap())
+            .unwrap();
+        assert!(finished.get());
+    }
 }
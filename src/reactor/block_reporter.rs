@@ -14,31 +14,91 @@
 
 //! This module is the implementation of block reporter reactor.
 //! It report blocks on block store contracts once blocks are generated by block chain.
+//! Depending on the reporting policy, it reports every block or only the checkpoints. A block
+//! store only justifies a checkpoint if all blocks between the source and the target of the vote
+//! are reported. Checkpoints are therefore reported together with their ancestors that are not
+//! reported yet. Other reactors can request additional blocks to be reported the same way.
 
-use futures::future::Either;
 use futures::future::IntoFuture;
-use futures::Future;
+use futures::future::{self, Either, Loop};
+use futures::prelude::*;
+use futures::stream;
 use rlp;
-use std::sync::Arc;
-use web3::contract::Contract;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 use web3::contract::Options;
-use web3::transports::Http;
-use web3::types::Address;
+use web3::types::{Address, BlockId, H256, U256};
 
+use config::ReportingPolicy;
+use ethereum::contract::{ContractEvent, ContractInstance};
+use ethereum::filter::EventFilter;
+use ethereum::transaction::{TransactionOutcome, TransactionStatus};
 use ethereum::types::block::Block;
+use ethereum::types::error::{Error, ErrorKind};
 use ethereum::Ethereum;
-use reactor::React;
-use web3::contract::Error;
+use reactor::{is_checkpoint, React, Reaction, ReactorOutcome};
 
-/// This is gas consumed by report block operation. This is calculated by estimate gas
-/// function from web3.
-const REPORT_BLOCK_ESTIMATED_GAS: i32 = 3_000_000;
+/// The blocks that other reactors requested to be reported, regardless of the reporting policy.
+/// Requesters do not wait for the reports. They observe the `BlockReported` events instead.
+#[derive(Clone, Default)]
+pub struct ReportRequests {
+    requests: Arc<Mutex<HashSet<H256>>>,
+}
+
+impl ReportRequests {
+    /// Requests a block to be reported together with its unreported ancestors with the next
+    /// observed block. Failed reports are tried again with the next observed block.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_hash` - The hash of the block to report.
+    pub fn request(&self, block_hash: H256) {
+        self.requests.lock().unwrap().insert(block_hash);
+    }
 
+    /// Removes and returns all requested blocks.
+    fn take(&self) -> HashSet<H256> {
+        self.requests.lock().unwrap().drain().collect()
+    }
+
+    /// Requests a block again if its report failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_hash` - The hash of the requested block.
+    /// * `reported` - Whether the block and its ancestors were reported.
+    fn settle(&self, block_hash: H256, reported: bool) {
+        if !reported {
+            warn!("Requesting report of block {:x} again", block_hash);
+            self.request(block_hash);
+        }
+    }
+}
+
+/// The blocks that are reported for an observed block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scope {
+    /// No block is reported.
+    Nothing,
+    /// Only the observed block is reported.
+    Block,
+    /// The observed block is reported together with its unreported ancestors.
+    Ancestry,
+}
+
+/// The block reporter reports the blocks of the observed chain on its block store on auxiliary,
+/// following its reporting policy and the requests of other reactors.
+#[derive(Clone)]
 pub struct BlockReporter {
-    block_store: Arc<Contract<Http>>,
+    block_store: Arc<ContractInstance>,
     from: Address,
-    event_loop: tokio_core::reactor::Handle,
+    /// The chain whose blocks are reported.
+    observed_chain: Arc<Ethereum>,
     block_chain: Arc<Ethereum>,
+    policy: ReportingPolicy,
+    /// The starting height and the epoch length of the block store. Read once they are needed.
+    epochs: Arc<Mutex<Option<(U256, U256)>>>,
+    requests: ReportRequests,
 }
 
 impl BlockReporter {
@@ -48,81 +108,428 @@ impl BlockReporter {
     ///
     /// * `block_store` - Contract instance of block store.
     /// * `from` - Address which does block reporting.
-    /// * `event_loop` - The reactor's event loop to handle the tasks spawned by this observer.
+    /// * `observed_chain` - Block chain object of the chain whose blocks are reported.
     /// * `block_chain` - Block chain object, where block reporting will happen.
+    /// * `policy` - The policy that defines which blocks are reported.
     pub fn new(
-        block_store: Arc<Contract<Http>>,
+        block_store: Arc<ContractInstance>,
         from: Address,
-        event_loop: tokio_core::reactor::Handle,
+        observed_chain: Arc<Ethereum>,
         block_chain: Arc<Ethereum>,
+        policy: ReportingPolicy,
     ) -> Self {
         BlockReporter {
             block_store,
             from,
-            event_loop,
+            observed_chain,
             block_chain,
+            policy,
+            epochs: Arc::new(Mutex::new(None)),
+            requests: ReportRequests::default(),
         }
     }
+
+    /// Returns a handle to request blocks to be reported.
+    pub fn requests(&self) -> ReportRequests {
+        self.requests.clone()
+    }
 }
 
-impl React for BlockReporter {
-    /// Defines logic of block reporting on block store contracts once a new block is generated.
+impl BlockReporter {
+    /// Reports the given block on the block store contract, unless it was already reported.
     ///
     /// # Arguments
     ///
-    /// * `block` - The observed block.
-    fn react(&self, block: &Block) {
-        info!("Reporting block for number {:?} ", block.number);
+    /// * `block` - The block to report.
+    ///
+    /// # Returns
+    ///
+    /// Returns a future of the outcome of the mined report transaction. The future resolves to
+    /// `None` if the block was already reported.
+    pub fn report(
+        &self,
+        block: &Block,
+    ) -> impl Future<Item = Option<TransactionOutcome>, Error = Error> {
+        report(
+            Arc::clone(&self.block_store),
+            self.from,
+            Arc::clone(&self.block_chain),
+            block,
+        )
+    }
 
-        let encoded_block = rlp::encode(block);
-        let block_hash = block.hash();
+    /// Reports the given block together with its ancestors that are not reported yet, in
+    /// ascending order. The ancestry ends at the first reported ancestor, which is the latest
+    /// justified checkpoint at the latest, as justified checkpoints are reported. It never
+    /// reaches below the head of the block store.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to report.
+    ///
+    /// # Returns
+    ///
+    /// Returns a future of the outcomes of all mined report transactions. Fails on the first
+    /// report that reverts, as the reports of its descendants would revert as well.
+    fn report_with_ancestry(
+        &self,
+        block: Block,
+    ) -> impl Future<Item = Vec<TransactionOutcome>, Error = Error> {
         let block_store = Arc::clone(&self.block_store);
-        let block_chain = &self.block_chain;
         let from = self.from;
+        let observed_chain = Arc::clone(&self.observed_chain);
+        let reporter = self.clone();
 
-        // Unlocking account with zero time interval, which unlocks account until node shuts down.
-        let call_future = block_chain.unlock_account(Some(0)).then(move |_| {
-            block_store
-                .query(
-                    "isBlockReported",
-                    block_hash,
-                    from,
-                    Options::default(),
-                    None,
-                ).then(
-                    move |result: Result<bool, web3::contract::Error>| match result {
-                        Ok(is_reported) => if is_reported {
-                            Either::A(Ok(()).into_future())
+        self.head_height()
+            .and_then(move |head_height| {
+                unreported_ancestry(block, head_height, move |parent_hash| {
+                    let observed_chain = Arc::clone(&observed_chain);
+                    is_reported(&block_store, from, parent_hash).and_then(move |is_reported| {
+                        if is_reported {
+                            Either::A(future::ok(None))
                         } else {
                             Either::B(
-                                block_store
-                                    .call(
-                                        "reportBlock",
-                                        encoded_block,
-                                        from,
-                                        Options::with(|opt| {
-                                            opt.gas = Some(REPORT_BLOCK_ESTIMATED_GAS.into())
-                                        }),
-                                    ).then(move |tx| {
-                                        info!("Block reported got tx: {:?}", tx);
-                                        Ok(())
-                                    }).map_err(|error: Error| {
-                                        error!("Error reporting block {:?}", error)
-                                    }),
+                                observed_chain
+                                    .block(BlockId::Hash(parent_hash), &EventFilter::default())
+                                    .map(Some),
                             )
-                        },
-                        Err(error) => {
-                            error!(
-                                "Error while checking if block is already reported{:?}",
-                                error
-                            );
-                            // Event loop spawn expects certain types. It doesn't support err types.
-                            Either::A(Ok(()).into_future())
                         }
-                    },
+                    })
+                })
+            }).and_then(move |ancestry| {
+                stream::iter_ok(ancestry)
+                    .and_then(move |block| {
+                        info!("Reporting block for number {:?} ", block.number);
+                        reporter.report(&block).and_then(succeeded)
+                    }).filter_map(|outcome| outcome)
+                    .collect()
+            })
+    }
+
+    /// Returns which blocks are reported for the given observed block.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The observed block.
+    /// * `is_requested` - Whether another reactor requested the block to be reported.
+    fn scope(&self, block: &Block, is_requested: bool) -> impl Future<Item = Scope, Error = Error> {
+        if is_requested {
+            return Either::A(future::ok(Scope::Ancestry));
+        }
+        if self.policy == ReportingPolicy::All {
+            return Either::A(future::ok(Scope::Block));
+        }
+
+        let height = block.number.low_u64();
+        Either::B(self.epochs().map(move |(starting_height, epoch_length)| {
+            if is_checkpoint(height, starting_height, epoch_length) {
+                Scope::Ancestry
+            } else {
+                Scope::Nothing
+            }
+        }))
+    }
+
+    /// Returns the starting height and the epoch length of the block store.
+    fn epochs(&self) -> impl Future<Item = (U256, U256), Error = Error> {
+        let cached_epochs = *self.epochs.lock().unwrap();
+        match cached_epochs {
+            Some(epochs) => Either::A(future::ok(epochs)),
+            None => {
+                let epochs = Arc::clone(&self.epochs);
+                let contract = self.block_store.contract();
+                let starting_height =
+                    contract.query("startingHeight", (), self.from, Options::default(), None);
+                let epoch_length =
+                    contract.query("epochLength", (), self.from, Options::default(), None);
+
+                Either::B(
+                    starting_height
+                        .join(epoch_length)
+                        .map_err(|error| {
+                            Error::new(
+                                ErrorKind::ContractError,
+                                format!("Error while reading epochs of block store: {}", error),
+                            )
+                        }).map(move |read_epochs| {
+                            *epochs.lock().unwrap() = Some(read_epochs);
+                            read_epochs
+                        }),
+                )
+            }
+        }
+    }
+
+    /// Returns the height of the head of the block store, which is its latest finalised
+    /// checkpoint.
+    fn head_height(&self) -> impl Future<Item = u64, Error = Error> {
+        let observed_chain = Arc::clone(&self.observed_chain);
+        self.block_store
+            .contract()
+            .query("getHead", (), self.from, Options::default(), None)
+            .map_err(|error| {
+                Error::new(
+                    ErrorKind::ContractError,
+                    format!("Error while reading head of block store: {}", error),
                 )
-        });
+            }).and_then(move |head: H256| {
+                observed_chain
+                    .block(BlockId::Hash(head), &EventFilter::default())
+                    .map(|head| head.number.low_u64())
+            })
+    }
+}
+
+impl React for BlockReporter {
+    /// Defines logic of block reporting on block store contracts once a new block is generated.
+    /// Also reports all blocks that were requested since the last block, together with their
+    /// unreported ancestors. Requests whose reports fail are tried again with the next block.
+    /// A report transaction that reverts fails the reaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The observed block.
+    fn react(&self, block: &Block, _events: &[ContractEvent]) -> Reaction {
+        let mut requested = self.requests.take();
+        let is_requested = requested.remove(&block.hash);
+
+        let reporter = self.clone();
+        let observed_block = block.clone();
+        let observed_report = self
+            .scope(block, is_requested)
+            .and_then(move |scope| match scope {
+                Scope::Nothing => Either::A(future::ok(vec![])),
+                Scope::Block => {
+                    info!("Reporting block for number {:?} ", observed_block.number);
+                    Either::B(Either::A(
+                        reporter
+                            .report(&observed_block)
+                            .and_then(succeeded)
+                            .map(|outcome| outcome.into_iter().collect()),
+                    ))
+                }
+                Scope::Ancestry => {
+                    Either::B(Either::B(reporter.report_with_ancestry(observed_block)))
+                }
+            }).then({
+                let requests = self.requests.clone();
+                let block_hash = block.hash;
+                move |result| {
+                    if is_requested {
+                        requests.settle(block_hash, result.is_ok());
+                    }
+                    result
+                }
+            });
+
+        let requested_reports: Vec<_> = requested
+            .into_iter()
+            .map(|block_hash| {
+                let reporter = self.clone();
+                let requests = self.requests.clone();
+                self.observed_chain
+                    .block(BlockId::Hash(block_hash), &EventFilter::default())
+                    .and_then(move |block| {
+                        info!("Reporting requested block {:x}", block.hash);
+                        reporter.report_with_ancestry(block)
+                    }).then(move |result| {
+                        requests.settle(block_hash, result.is_ok());
+                        result
+                    })
+            }).collect();
+
+        Box::new(
+            observed_report
+                .join(future::join_all(requested_reports))
+                .map(|(mut outcomes, requested_outcomes)| {
+                    outcomes.extend(requested_outcomes.into_iter().flatten());
+
+                    if outcomes.is_empty() {
+                        ReactorOutcome::Skipped
+                    } else {
+                        let reported: Vec<String> = outcomes
+                            .iter()
+                            .map(|outcome| format!("Block reported: {}", outcome))
+                            .collect();
+                        ReactorOutcome::Acted(reported.join(", "))
+                    }
+                }),
+        )
+    }
+}
+
+/// Collects a block and its ancestors that still need to be reported, in ascending order.
+/// Walks back from the block until an ancestor is reported or until it reaches the lower bound.
+///
+/// # Arguments
+///
+/// * `block` - The block to report.
+/// * `lower_bound` - The height below which all blocks are reported. Ancestors at this height or
+/// below are not collected.
+/// * `unreported_parent` - Retrieves the parent with the given hash, or `None` if it is reported.
+fn unreported_ancestry<F, P>(
+    block: Block,
+    lower_bound: u64,
+    unreported_parent: F,
+) -> impl Future<Item = Vec<Block>, Error = Error>
+where
+    F: Fn(H256) -> P,
+    P: Future<Item = Option<Block>, Error = Error>,
+{
+    future::loop_fn(vec![block], move |mut ancestry| {
+        let (height, parent_hash) = {
+            let oldest = &ancestry[ancestry.len() - 1];
+            (oldest.number.low_u64(), oldest.parent_hash)
+        };
+        if height <= lower_bound + 1 {
+            return Either::A(future::ok(Loop::Break(ancestry)));
+        }
+
+        Either::B(
+            unreported_parent(parent_hash).map(move |parent| match parent {
+                Some(parent) => {
+                    ancestry.push(parent);
+                    Loop::Continue(ancestry)
+                }
+                None => Loop::Break(ancestry),
+            }),
+        )
+    }).map(|mut ancestry| {
+        ancestry.reverse();
+        ancestry
+    })
+}
+
+/// Fails if a report transaction was reverted.
+fn succeeded(outcome: Option<TransactionOutcome>) -> Result<Option<TransactionOutcome>, Error> {
+    match outcome {
+        Some(ref outcome) if outcome.status == TransactionStatus::Reverted => Err(Error::new(
+            ErrorKind::TransactionError,
+            format!("Block report reverted: {}", outcome),
+        )),
+        _ => Ok(outcome),
+    }
+}
+
+/// Checks whether a block is reported on a block store contract.
+///
+/// # Arguments
+///
+/// * `block_store` - Contract instance of block store.
+/// * `from` - Address which does block reporting.
+/// * `block_hash` - The hash of the block.
+fn is_reported(
+    block_store: &ContractInstance,
+    from: Address,
+    block_hash: H256,
+) -> impl Future<Item = bool, Error = Error> {
+    block_store
+        .contract()
+        .query(
+            "isBlockReported",
+            block_hash,
+            from,
+            Options::default(),
+            None,
+        ).map_err(|error| {
+            Error::new(
+                ErrorKind::ContractError,
+                format!(
+                    "Error while checking if block is already reported: {}",
+                    error
+                ),
+            )
+        })
+}
+
+/// Reports a block on a block store contract, unless it was already reported.
+///
+/// # Arguments
+///
+/// * `block_store` - Contract instance of block store.
+/// * `from` - Address which does block reporting.
+/// * `block_chain` - Block chain object, where block reporting will happen.
+/// * `block` - The block to report.
+fn report(
+    block_store: Arc<ContractInstance>,
+    from: Address,
+    block_chain: Arc<Ethereum>,
+    block: &Block,
+) -> impl Future<Item = Option<TransactionOutcome>, Error = Error> {
+    let encoded_block = rlp::encode(block);
+    let block_hash = block.hash();
+
+    is_reported(&block_store, from, block_hash).and_then(move |is_reported| {
+        if is_reported {
+            Either::A(Ok(None).into_future())
+        } else {
+            Either::B(
+                block_store
+                    .encode_input("reportBlock", encoded_block)
+                    .into_future()
+                    .and_then(move |data| {
+                        block_chain.submit_transaction(block_store.address(), data)
+                    }).map(Some),
+            )
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    use ethereum::types::block::fixtures::block;
+
+    /// Returns the numbers of the unreported ancestry of the given block.
+    fn ancestry(number: u64, lower_bound: u64, reported: &[u64]) -> Vec<u64> {
+        let reported: HashSet<H256> = reported.iter().map(|number| H256::from(*number)).collect();
+        let blocks: HashMap<H256, Block> = (1..number)
+            .map(|number| (H256::from(number), block(number)))
+            .collect();
+        unreported_ancestry(block(number), lower_bound, |parent_hash| {
+            if reported.contains(&parent_hash) {
+                future::ok(None)
+            } else {
+                future::ok(blocks.get(&parent_hash).cloned())
+            }
+        }).wait()
+        .unwrap()
+        .iter()
+        .map(|block| block.number.low_u64())
+        .collect()
+    }
+
+    #[test]
+    fn the_ancestry_ends_at_the_first_reported_ancestor() {
+        assert_eq!(ancestry(10, 2, &[5]), vec![6, 7, 8, 9, 10]);
+        assert_eq!(ancestry(10, 2, &[9]), vec![10]);
+        // The head of the block store bounds the ancestry.
+        assert_eq!(ancestry(10, 7, &[]), vec![8, 9, 10]);
+        assert_eq!(ancestry(10, 10, &[]), vec![10]);
+    }
+
+    #[test]
+    fn requests_are_repeated_until_the_block_is_reported() {
+        let requests = ReportRequests::default();
+        requests.request(H256::from(1));
+        requests.request(H256::from(2));
+        requests.request(H256::from(2));
+
+        let requested = requests.take();
+        assert_eq!(requested.len(), 2);
+        assert!(requests.take().is_empty());
+
+        // A failed report is requested again.
+        requests.settle(H256::from(1), false);
+        requests.settle(H256::from(2), true);
+
+        let requested = requests.take();
+        assert_eq!(requested.len(), 1);
+        assert!(requested.contains(&H256::from(1)));
+        requests.settle(H256::from(1), true);
 
-        &self.event_loop.spawn(call_future);
+        assert!(requests.take().is_empty());
     }
 }
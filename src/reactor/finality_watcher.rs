@@ -0,0 +1,457 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module is the implementation of the finality watcher reactor.
+//! It tracks the checkpoints that the block stores justify and finalise. Both block stores live
+//! on auxiliary, so the finality watcher reacts on auxiliary blocks. The highest checkpoints are
+//! persisted in the state store. A checkpoint only replaces a stored one if it is higher, so that
+//! replaying or processing blocks again never moves the checkpoints backwards.
+
+use futures::future;
+use futures::prelude::*;
+use std::fmt;
+use std::sync::Arc;
+use web3::types::{BlockId, H256};
+
+use config::Chain;
+use ethereum::contract::{BlockStoreEvent, ContractEvent, ContractInstance, ContractType};
+use ethereum::filter::{EventFilter, EventSubscription};
+use ethereum::types::block::Block;
+use ethereum::types::error::Error;
+use ethereum::Ethereum;
+use observer::chain::Reorg;
+use reactor::{React, Reaction, ReactorOutcome};
+use state::{ChainState, Checkpoint, StateStore};
+
+/// The number of replaced checkpoints that are kept per chain and status, so that they can be
+/// restored after a reorg. A reorg that drops more checkpoints forgets the older ones.
+const MAX_PREVIOUS_CHECKPOINTS: usize = 128;
+
+/// A view of the highest justified and finalised checkpoints of both chains.
+/// Other reactors use it to act on the finality of the chains.
+#[derive(Clone)]
+pub struct Finality {
+    state: Arc<StateStore>,
+}
+
+impl Finality {
+    /// Creates a new view of the checkpoints that are stored in the given state store.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state store that persists the checkpoints.
+    pub fn new(state: Arc<StateStore>) -> Self {
+        Finality { state }
+    }
+
+    /// Returns the highest justified checkpoint of the given chain.
+    pub fn justified_checkpoint(&self, chain: Chain) -> Option<Checkpoint> {
+        self.state.chain(chain).justified_checkpoint
+    }
+
+    /// Returns the highest finalised checkpoint of the given chain.
+    pub fn finalised_checkpoint(&self, chain: Chain) -> Option<Checkpoint> {
+        self.state.chain(chain).finalised_checkpoint
+    }
+}
+
+/// Whether a block store justified or finalised a checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Justified,
+    Finalised,
+}
+
+impl Status {
+    /// Returns the stored checkpoint of this status.
+    fn get(self, state: &ChainState) -> Option<Checkpoint> {
+        match self {
+            Status::Justified => state.justified_checkpoint,
+            Status::Finalised => state.finalised_checkpoint,
+        }
+    }
+
+    /// Returns the stored checkpoint of this status together with the checkpoints it replaced.
+    fn checkpoints(
+        self,
+        state: &mut ChainState,
+    ) -> (&mut Option<Checkpoint>, &mut Vec<Checkpoint>) {
+        match self {
+            Status::Justified => (
+                &mut state.justified_checkpoint,
+                &mut state.previous_justified_checkpoints,
+            ),
+            Status::Finalised => (
+                &mut state.finalised_checkpoint,
+                &mut state.previous_finalised_checkpoints,
+            ),
+        }
+    }
+
+    /// Replaces the stored checkpoint of this status and keeps the replaced one.
+    fn store(self, state: &mut ChainState, checkpoint: Checkpoint) {
+        let (stored, previous) = self.checkpoints(state);
+        if let Some(replaced) = stored.take() {
+            previous.push(replaced);
+            if previous.len() > MAX_PREVIOUS_CHECKPOINTS {
+                previous.remove(0);
+            }
+        }
+        *stored = Some(checkpoint);
+    }
+
+    /// Forgets the checkpoints of this status whose events were emitted at or after the given
+    /// auxiliary block and restores the latest one that remains.
+    ///
+    /// # Returns
+    ///
+    /// Returns the restored checkpoint or `None` if no previous checkpoint remains.
+    fn forget(self, state: &mut ChainState, first_dropped: u64) -> Option<Checkpoint> {
+        let (stored, previous) = self.checkpoints(state);
+        previous.retain(|checkpoint| checkpoint.event_block < first_dropped);
+        *stored = previous.pop();
+        *stored
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Justified => write!(f, "justified"),
+            Status::Finalised => write!(f, "finalised"),
+        }
+    }
+}
+
+/// The finality watcher stores the checkpoints of the justified and finalised events of the block
+/// stores.
+pub struct FinalityWatcher {
+    subscriptions: Vec<EventSubscription>,
+    state: Arc<StateStore>,
+    origin: Arc<Ethereum>,
+    auxiliary: Arc<Ethereum>,
+}
+
+impl FinalityWatcher {
+    /// Creates a new instance of FinalityWatcher
+    ///
+    /// # Arguments
+    ///
+    /// * `block_stores` - Contract instances of the origin and the auxiliary block store.
+    /// * `origin` - Block chain object that points to origin, where the heights of origin
+    /// checkpoints are retrieved from.
+    /// * `auxiliary` - Block chain object that points to auxiliary, where the heights of
+    /// auxiliary checkpoints are retrieved from.
+    /// * `state` - The state store that persists the checkpoints.
+    pub fn new(
+        block_stores: &[Arc<ContractInstance>],
+        origin: Arc<Ethereum>,
+        auxiliary: Arc<Ethereum>,
+        state: Arc<StateStore>,
+    ) -> Result<Self, Error> {
+        let mut subscriptions = Vec::new();
+        for block_store in block_stores {
+            subscriptions.push(block_store.subscription("BlockJustified")?);
+            subscriptions.push(block_store.subscription("BlockFinalised")?);
+        }
+
+        Ok(FinalityWatcher {
+            subscriptions,
+            state,
+            origin,
+            auxiliary,
+        })
+    }
+
+    /// Returns a view of the checkpoints that this finality watcher tracks.
+    pub fn finality(&self) -> Finality {
+        Finality::new(Arc::clone(&self.state))
+    }
+}
+
+impl React for FinalityWatcher {
+    /// Stores the checkpoints that the block stores justified or finalised in the block if they
+    /// are higher than the stored ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The observed block.
+    /// * `events` - The decoded events of the block.
+    fn react(&self, block: &Block, events: &[ContractEvent]) -> Reaction {
+        let event_block = block.number.low_u64();
+        let checkpoints: Vec<_> = events
+            .iter()
+            .filter_map(|contract_event| {
+                let (status, hash) = match contract_event.event {
+                    BlockStoreEvent::BlockJustified { block_hash } => {
+                        (Status::Justified, block_hash)
+                    }
+                    BlockStoreEvent::BlockFinalised { block_hash } => {
+                        (Status::Finalised, block_hash)
+                    }
+                    BlockStoreEvent::BlockReported { .. } => return None,
+                };
+                let (chain, block_chain) = match contract_event.contract_type {
+                    ContractType::OriginBlockStore => (Chain::Origin, &self.origin),
+                    ContractType::AuxiliaryBlockStore => (Chain::Auxiliary, &self.auxiliary),
+                };
+
+                Some(checkpoint_height(block_chain, hash).map(move |height| {
+                    let checkpoint = Checkpoint {
+                        hash,
+                        height,
+                        event_block,
+                    };
+                    (chain, status, checkpoint)
+                }))
+            }).collect();
+
+        let state = Arc::clone(&self.state);
+        Box::new(
+            future::join_all(checkpoints)
+                .and_then(move |checkpoints| track(&state, &checkpoints))
+                .map(|tracked| {
+                    for checkpoint in &tracked {
+                        info!("{}", checkpoint);
+                    }
+
+                    if tracked.is_empty() {
+                        ReactorOutcome::Skipped
+                    } else {
+                        ReactorOutcome::Acted(tracked.join(", "))
+                    }
+                }),
+        )
+    }
+
+    fn subscriptions(&self) -> Vec<EventSubscription> {
+        self.subscriptions.clone()
+    }
+
+    /// Forgets the checkpoints whose events were emitted in blocks that were dropped from
+    /// auxiliary. The blocks of the new canonical branch are handed to `react` afterwards, so
+    /// that their checkpoints are tracked again.
+    ///
+    /// # Arguments
+    ///
+    /// * `reorg` - The dropped and the added blocks of the reorganisation.
+    fn react_to_reorg(&self, reorg: &Reorg) {
+        let first_dropped = match reorg.dropped.first() {
+            Some(block) => block.number.low_u64(),
+            None => return,
+        };
+
+        match forget(&self.state, first_dropped) {
+            Ok(forgotten) => {
+                for checkpoint in forgotten {
+                    warn!("{}", checkpoint);
+                }
+            }
+            Err(error) => error!("Error when forgetting reorganised checkpoints: {}", error),
+        }
+    }
+}
+
+/// Retrieves the height of a checkpoint from its chain.
+fn checkpoint_height(block_chain: &Ethereum, hash: H256) -> impl Future<Item = u64, Error = Error> {
+    block_chain
+        .block(BlockId::from(hash), &EventFilter::default())
+        .map(|block| block.number.low_u64())
+}
+
+/// Stores the given checkpoints where they are higher than the stored ones.
+///
+/// # Arguments
+///
+/// * `state` - The state store that persists the checkpoints.
+/// * `checkpoints` - The justified and finalised checkpoints of the events of a block.
+///
+/// # Returns
+///
+/// Returns a description of every stored checkpoint.
+fn track(
+    state: &StateStore,
+    checkpoints: &[(Chain, Status, Checkpoint)],
+) -> Result<Vec<String>, Error> {
+    let mut tracked = Vec::new();
+    for &(chain, status, checkpoint) in checkpoints {
+        if let Some(stored) = status.get(&state.chain(chain)) {
+            if checkpoint.height <= stored.height {
+                debug!(
+                    "Not storing {} {} checkpoint {:x}, it is not higher than {:x}",
+                    status, chain, checkpoint.hash, stored.hash
+                );
+                continue;
+            }
+        }
+
+        state.update(chain, |state| status.store(state, checkpoint))?;
+        tracked.push(format!(
+            "Stored {} {} checkpoint {:x} at height {}",
+            status, chain, checkpoint.hash, checkpoint.height
+        ));
+    }
+
+    Ok(tracked)
+}
+
+/// Forgets all checkpoints whose events were emitted at or after the given auxiliary block and
+/// restores the checkpoints that they replaced.
+///
+/// # Arguments
+///
+/// * `state` - The state store that persists the checkpoints.
+/// * `first_dropped` - The number of the first auxiliary block that was dropped in a reorg.
+///
+/// # Returns
+///
+/// Returns a description of every forgotten checkpoint.
+fn forget(state: &StateStore, first_dropped: u64) -> Result<Vec<String>, Error> {
+    let mut forgotten = Vec::new();
+    for &chain in &[Chain::Origin, Chain::Auxiliary] {
+        for &status in &[Status::Justified, Status::Finalised] {
+            if let Some(stored) = status.get(&state.chain(chain)) {
+                if stored.event_block >= first_dropped {
+                    let mut restored = None;
+                    state.update(chain, |state| {
+                        restored = status.forget(state, first_dropped)
+                    })?;
+                    let restored = match restored {
+                        Some(checkpoint) => format!(
+                            "restored {:x} at height {}",
+                            checkpoint.hash, checkpoint.height
+                        ),
+                        None => "no previous checkpoint remains".to_string(),
+                    };
+                    forgotten.push(format!(
+                        "Forgot {} {} checkpoint {:x}, its event was dropped in a reorg, {}",
+                        status, chain, stored.hash, restored
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(forgotten)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn checkpoint(hash: u64, height: u64, event_block: u64) -> Checkpoint {
+        Checkpoint {
+            hash: H256::from(hash),
+            height,
+            event_block,
+        }
+    }
+
+    #[test]
+    fn the_finality_watcher_stores_the_highest_checkpoints() {
+        let path = env::temp_dir().join(format!(
+            "mosaic_the_finality_watcher_stores_the_highest_checkpoints_{}.json",
+            process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let state = Arc::new(StateStore::open(&path).unwrap());
+        let finality = Finality::new(Arc::clone(&state));
+
+        let tracked = track(
+            &state,
+            &[
+                (Chain::Origin, Status::Finalised, checkpoint(1, 10, 100)),
+                (Chain::Origin, Status::Finalised, checkpoint(2, 20, 100)),
+                (Chain::Auxiliary, Status::Justified, checkpoint(3, 30, 100)),
+            ],
+        ).unwrap();
+        assert_eq!(tracked.len(), 3);
+
+        // Replaying an older block does not move the checkpoints backwards.
+        let tracked = track(
+            &state,
+            &[
+                (Chain::Origin, Status::Finalised, checkpoint(1, 10, 90)),
+                (Chain::Auxiliary, Status::Justified, checkpoint(3, 30, 90)),
+            ],
+        ).unwrap();
+        assert!(tracked.is_empty());
+
+        assert_eq!(
+            finality.finalised_checkpoint(Chain::Origin),
+            Some(checkpoint(2, 20, 100))
+        );
+        assert_eq!(finality.justified_checkpoint(Chain::Origin), None);
+        assert_eq!(
+            finality.justified_checkpoint(Chain::Auxiliary),
+            Some(checkpoint(3, 30, 100))
+        );
+        assert_eq!(finality.finalised_checkpoint(Chain::Auxiliary), None);
+
+        // The checkpoints are persisted.
+        let stored = StateStore::open(&path).unwrap();
+        assert_eq!(
+            stored.chain(Chain::Origin).finalised_checkpoint,
+            Some(checkpoint(2, 20, 100))
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checkpoints_of_reorganised_blocks_are_forgotten() {
+        let path = env::temp_dir().join(format!(
+            "mosaic_checkpoints_of_reorganised_blocks_are_forgotten_{}.json",
+            process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let state = StateStore::open(&path).unwrap();
+        track(
+            &state,
+            &[
+                (Chain::Origin, Status::Justified, checkpoint(5, 4, 95)),
+                (Chain::Origin, Status::Justified, checkpoint(6, 6, 100)),
+                (Chain::Origin, Status::Justified, checkpoint(1, 10, 100)),
+                (Chain::Origin, Status::Finalised, checkpoint(2, 5, 101)),
+                (Chain::Auxiliary, Status::Justified, checkpoint(3, 30, 99)),
+            ],
+        ).unwrap();
+
+        assert_eq!(forget(&state, 100).unwrap().len(), 2);
+        // The previous checkpoints whose events were not dropped are restored.
+        assert_eq!(
+            state.chain(Chain::Origin).justified_checkpoint,
+            Some(checkpoint(5, 4, 95))
+        );
+        assert_eq!(state.chain(Chain::Origin).finalised_checkpoint, None);
+        assert_eq!(
+            state.chain(Chain::Auxiliary).justified_checkpoint,
+            Some(checkpoint(3, 30, 99))
+        );
+
+        // A lower checkpoint of the new branch is stored again.
+        let tracked = track(
+            &state,
+            &[(Chain::Origin, Status::Justified, checkpoint(4, 8, 100))],
+        ).unwrap();
+        assert_eq!(tracked.len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -15,60 +15,92 @@
 //! This module is about different kinds of block and event reactors.
 //! A reactor is a reaction defined on block generation.
 //! To add new reactor, implement react trait and register it with block chain.
-//! Reactors are expected to handle error internally.
+//! Reactors return a future of their reaction, so that the observer knows when they finished.
 
-use ethereum::contract::{ContractRegistry, ContractType};
+use config::Chain;
+use ethereum::contract::{ContractEvent, ContractRegistry, ContractType};
+use ethereum::filter::EventSubscription;
 use ethereum::types::block::Block;
 use ethereum::types::error::Error;
 use ethereum::Ethereum;
+use futures::Future;
+use observer::chain::Reorg;
 use reactor::block_reporter::BlockReporter;
+use reactor::finality_watcher::FinalityWatcher;
+use reactor::voter::{BlockStore, Voter};
+use state::StateStore;
+use std::fmt;
 use std::sync::Arc;
+use web3::types::U256;
 use Config;
 
-mod block_reporter;
+pub mod block_reporter;
+pub mod finality_watcher;
+pub mod voter;
+
+/// The future of the reaction of a reactor on a block.
+pub type Reaction = Box<Future<Item = ReactorOutcome, Error = Error>>;
+
+/// The reactors that react on the blocks of a chain.
+pub type Reactors = Vec<Box<React>>;
+
+/// The outcome of a successful reaction of a reactor on a block.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReactorOutcome {
+    /// The reactor had nothing to do for the block.
+    Skipped,
+    /// The reactor acted on the block. The description says what it did.
+    Acted(String),
+}
+
+impl fmt::Display for ReactorOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReactorOutcome::Skipped => write!(f, "skipped"),
+            ReactorOutcome::Acted(description) => write!(f, "{}", description),
+        }
+    }
+}
 
 /// Anything that wants to react on block generation should implement this.
 pub trait React {
     /// Defines how different reactor will react on block observation.
     /// # Arguments
     ///
-    /// * `block` - The observed block.
-    fn react(&self, block: &Block);
+    /// * `block` - The observed block. It only contains the events that the reactor subscribed to.
+    /// * `events` - The decoded events of the block that were emitted by known contracts.
+    ///
+    /// # Returns
+    ///
+    /// Returns a future that resolves once the reactor is done with the block. The observer only
+    /// marks the block as processed if the reactions of all reactors succeed.
+    fn react(&self, block: &Block, events: &[ContractEvent]) -> Reaction;
+
+    /// Defines the events that the reactor reacts on. Only these events are retrieved from the
+    /// node and handed to `react`. Subscribes to no events by default.
+    fn subscriptions(&self) -> Vec<EventSubscription> {
+        vec![]
+    }
+
+    /// Defines how different reactor will react on a chain reorganisation.
+    /// The blocks of the new canonical branch are handed to `react` afterwards.
+    /// Does nothing by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `reorg` - The dropped and the added blocks of the reorganisation.
+    fn react_to_reorg(&self, _reorg: &Reorg) {}
 }
 
-/// Instantiate reactors which will react on origin block generation.
-///
-/// # Arguments
-///
-/// * `origin` - A blockchain object that points to origin.
-/// * `auxiliary` - A blockchain object that points to auxiliary.
-/// * `contract_registry` - Contract instances registry.
-/// * `config` - A configuration to register reactors.
-/// * `event_loop` - A configuration to register reactors.
-pub fn origin_reactors(
-    _origin: Arc<Ethereum>,
-    auxiliary: Arc<Ethereum>,
-    contract_registry: &ContractRegistry,
-    config: &Config,
-    event_loop: tokio_core::reactor::Handle,
-) -> Result<Vec<Box<React>>, Error> {
-    let mut origin_reactors: Vec<Box<React>> = Vec::new();
-
-    contract_registry
-        .get(&ContractType::OriginBlockStore)
-        .map(move |contract| {
-            let block_reporter = BlockReporter::new(
-                contract,
-                config.auxiliary_validator_address(),
-                event_loop,
-                auxiliary,
-            );
-            origin_reactors.push(Box::new(block_reporter));
-            Ok(origin_reactors)
-        })?
+/// Returns true if the block at the given height is a checkpoint after the starting height.
+pub fn is_checkpoint(height: u64, starting_height: U256, epoch_length: U256) -> bool {
+    let height = U256::from(height);
+    !epoch_length.is_zero()
+        && height > starting_height
+        && ((height - starting_height) % epoch_length).is_zero()
 }
 
-/// Instantiate reactors which will react on auxiliary block generation.
+/// Instantiate the reactors which will react on origin and on auxiliary block generation.
 ///
 /// # Arguments
 ///
@@ -76,28 +108,89 @@ pub fn origin_reactors(
 /// * `auxiliary` - A blockchain object that points to auxiliary.
 /// * `contract_registry` - Contract instances registry.
 /// * `config` - A configuration to register reactors.
-/// * `event_loop` - A configuration to register reactors.
-pub fn auxiliary_reactors(
-    _origin: Arc<Ethereum>,
+/// * `state` - The state store that persists the checkpoints of the finality watcher.
+///
+/// # Returns
+///
+/// Returns a tuple of the origin and the auxiliary reactors.
+pub fn reactors(
+    origin: Arc<Ethereum>,
     auxiliary: Arc<Ethereum>,
     contract_registry: &ContractRegistry,
     config: &Config,
-    event_loop: tokio_core::reactor::Handle,
-) -> Result<Vec<Box<React>>, Error> {
-    let mut auxiliary_reactors: Vec<Box<React>> = Vec::new();
-
-    contract_registry
-        .get(&ContractType::AuxiliaryBlockStore)
-        .map({
-            move |contract| {
-                let block_reporter = BlockReporter::new(
-                    contract,
-                    config.auxiliary_validator_address(),
-                    event_loop,
-                    auxiliary,
-                );
-                auxiliary_reactors.push(Box::new(block_reporter));
-                Ok(auxiliary_reactors)
-            }
-        })?
+    state: Arc<StateStore>,
+) -> Result<(Reactors, Reactors), Error> {
+    // Both block stores live on auxiliary and emit their events there.
+    let origin_block_store = contract_registry.get(&ContractType::OriginBlockStore)?;
+    let auxiliary_block_store = contract_registry.get(&ContractType::AuxiliaryBlockStore)?;
+
+    let origin_block_reporter = BlockReporter::new(
+        Arc::clone(&origin_block_store),
+        config.auxiliary_validator_address(),
+        Arc::clone(&origin),
+        Arc::clone(&auxiliary),
+        config.origin_reporting_policy(),
+    );
+    let auxiliary_block_reporter = BlockReporter::new(
+        Arc::clone(&auxiliary_block_store),
+        config.auxiliary_validator_address(),
+        Arc::clone(&auxiliary),
+        Arc::clone(&auxiliary),
+        config.auxiliary_reporting_policy(),
+    );
+
+    let finality_watcher = FinalityWatcher::new(
+        &[
+            Arc::clone(&origin_block_store),
+            Arc::clone(&auxiliary_block_store),
+        ],
+        Arc::clone(&origin),
+        Arc::clone(&auxiliary),
+        state,
+    )?;
+    let voter = Voter::new(
+        BlockStore::new(
+            Chain::Origin,
+            origin_block_store,
+            origin,
+            origin_block_reporter.requests(),
+        ),
+        BlockStore::new(
+            Chain::Auxiliary,
+            auxiliary_block_store,
+            Arc::clone(&auxiliary),
+            auxiliary_block_reporter.requests(),
+        ),
+        config.auxiliary_validator_address(),
+        finality_watcher.finality(),
+        auxiliary,
+    );
+
+    let origin_reactors: Reactors = vec![Box::new(origin_block_reporter)];
+    let auxiliary_reactors: Reactors = vec![
+        Box::new(finality_watcher),
+        Box::new(voter),
+        Box::new(auxiliary_block_reporter),
+    ];
+
+    Ok((origin_reactors, auxiliary_reactors))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checkpoints_are_at_epoch_boundaries() {
+        let starting_height = U256::from(100);
+        let epoch_length = U256::from(10);
+
+        assert!(is_checkpoint(110, starting_height, epoch_length));
+        assert!(is_checkpoint(200, starting_height, epoch_length));
+        assert!(!is_checkpoint(105, starting_height, epoch_length));
+        // The starting block is justified when the block store is deployed.
+        assert!(!is_checkpoint(100, starting_height, epoch_length));
+        assert!(!is_checkpoint(90, starting_height, epoch_length));
+        assert!(!is_checkpoint(110, starting_height, U256::zero()));
+    }
 }
@@ -0,0 +1,580 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module is the implementation of the voter reactor.
+//! It casts the votes of the validator to justify checkpoints. A checkpoint is a block at an epoch
+//! boundary, i.e. at a height `startingHeight + n * epochLength`. Once a checkpoint was reported
+//! on its block store, the voter votes for it as target with the highest justified checkpoint as
+//! source. Checkpoints at or below the highest finalised checkpoint are not voted for. The vote
+//! is signed with the key of the validator and sent to the polling place. If the block store
+//! does not accept the vote, the voter requests the ancestry of the target to be reported without
+//! waiting for it. The vote stays pending and is checked again whenever blocks are reported on
+//! the block store, until it is cast or the target is finalised.
+
+use ethabi::{self, Token};
+use futures::future::{self, Either};
+use futures::prelude::*;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tiny_keccak::Keccak;
+use web3::contract::tokens::{Detokenize, Tokenize};
+use web3::contract::Options;
+use web3::types::{Address, BlockId, Bytes, H160, H256, U256};
+
+use config::Chain;
+use ethereum::contract::{BlockStoreEvent, ContractEvent, ContractInstance, ContractType};
+use ethereum::filter::{EventFilter, EventSubscription};
+use ethereum::transaction::TransactionStatus;
+use ethereum::types::block::Block;
+use ethereum::types::error::{Error, ErrorKind};
+use ethereum::types::Signature;
+use ethereum::Ethereum;
+use reactor::block_reporter::ReportRequests;
+use reactor::finality_watcher::Finality;
+use reactor::{is_checkpoint, React, Reaction, ReactorOutcome};
+use state::Checkpoint;
+
+/// The type of the vote message that the validator signs. It is the type that the polling place
+/// hashes into `VOTE_MESSAGE_TYPEHASH` in `contracts/gateway/PollingPlace.sol` of the OpenST
+/// mosaic-contracts, whose `vote` function is described by `PollingPlace.json`.
+const VOTE_MESSAGE_TYPE: &str = concat!(
+    "VoteMessage(bytes20 coreIdentifier,bytes32 transitionHash,bytes32 source,bytes32 target,",
+    "uint256 sourceHeight,uint256 targetHeight)"
+);
+
+/// A vote of a validator to justify a target checkpoint, based on a justified source checkpoint.
+#[derive(Debug, Clone, PartialEq)]
+struct Vote {
+    /// The identifier of the core of the auxiliary chain that the vote is cast for.
+    core_identifier: H160,
+    transition_hash: H256,
+    source: H256,
+    target: H256,
+    source_height: U256,
+    target_height: U256,
+}
+
+impl Vote {
+    /// Returns the hash of the vote message that the validator signs. It is hashed the same way
+    /// as the polling place hashes it in `hashVoteMessage`, i.e. `keccak256(abi.encode(typeHash,
+    /// coreIdentifier, transitionHash, source, target, sourceHeight, targetHeight))`.
+    /// The polling place recovers the validator from the hash prefixed as by `eth_sign`, which
+    /// is how `Signer::sign` signs it.
+    fn message_hash(&self) -> H256 {
+        let encoded = ethabi::encode(&[
+            Token::FixedBytes(keccak256(VOTE_MESSAGE_TYPE.as_bytes()).to_vec()),
+            Token::FixedBytes(self.core_identifier.to_vec()),
+            Token::FixedBytes(self.transition_hash.to_vec()),
+            Token::FixedBytes(self.source.to_vec()),
+            Token::FixedBytes(self.target.to_vec()),
+            Token::Uint(self.source_height),
+            Token::Uint(self.target_height),
+        ]);
+
+        keccak256(&encoded)
+    }
+}
+
+/// Splits a signature into the `v`, `r`, and `s` parameters that the polling place passes to
+/// `ecrecover`.
+fn signature_parameters(signature: &Signature) -> (U256, H256, H256) {
+    (
+        U256::from(signature[64]),
+        H256::from(&signature[0..32]),
+        H256::from(&signature[32..64]),
+    )
+}
+
+/// A block store together with the chain whose blocks it stores and the connection to that
+/// chain.
+pub struct BlockStore {
+    chain: Chain,
+    contract_type: ContractType,
+    contract: Arc<ContractInstance>,
+    /// The connection to the chain whose blocks are stored in the block store.
+    block_chain: Arc<Ethereum>,
+    /// Requests blocks of the chain to be reported on the block store.
+    requests: ReportRequests,
+    /// The starting height and the epoch length of the block store. Read once they are needed.
+    epochs: Mutex<Option<(U256, U256)>>,
+    /// The checkpoints whose votes the block store did not accept yet.
+    pending_votes: Mutex<HashSet<H256>>,
+}
+
+impl BlockStore {
+    /// Creates a new block store that the voter votes on.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The chain whose blocks are stored in the block store.
+    /// * `contract` - Contract instance of the block store.
+    /// * `block_chain` - Block chain object that points to the chain of the block store.
+    /// * `requests` - The requests of the block reporter of the chain.
+    pub fn new(
+        chain: Chain,
+        contract: Arc<ContractInstance>,
+        block_chain: Arc<Ethereum>,
+        requests: ReportRequests,
+    ) -> Self {
+        let contract_type = match chain {
+            Chain::Origin => ContractType::OriginBlockStore,
+            Chain::Auxiliary => ContractType::AuxiliaryBlockStore,
+        };
+
+        BlockStore {
+            chain,
+            contract_type,
+            contract,
+            block_chain,
+            requests,
+            epochs: Mutex::new(None),
+            pending_votes: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+/// The voter votes on the checkpoints of both block stores.
+pub struct Voter {
+    block_stores: Vec<Arc<BlockStore>>,
+    from: Address,
+    finality: Finality,
+    auxiliary: Arc<Ethereum>,
+}
+
+impl Voter {
+    /// Creates a new instance of Voter
+    ///
+    /// # Arguments
+    ///
+    /// * `origin_block_store` - The block store of origin.
+    /// * `auxiliary_block_store` - The block store of auxiliary.
+    /// * `from` - Address of the validator that votes.
+    /// * `finality` - The highest justified checkpoints, which are used as sources of the votes,
+    /// and the highest finalised checkpoints, below which no votes are cast.
+    /// * `auxiliary` - Block chain object that points to auxiliary, where votes are sent to.
+    pub fn new(
+        origin_block_store: BlockStore,
+        auxiliary_block_store: BlockStore,
+        from: Address,
+        finality: Finality,
+        auxiliary: Arc<Ethereum>,
+    ) -> Self {
+        Voter {
+            block_stores: vec![
+                Arc::new(origin_block_store),
+                Arc::new(auxiliary_block_store),
+            ],
+            from,
+            finality,
+            auxiliary,
+        }
+    }
+
+    /// Votes for a reported block if it is a checkpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_store` - The block store that the block was reported on.
+    /// * `target` - The hash of the reported block.
+    ///
+    /// # Returns
+    ///
+    /// Returns a future of a description of the vote, or `None` if the block is no checkpoint, if
+    /// it is not above the finalised checkpoint, or if the block store does not accept the vote
+    /// yet.
+    fn vote(
+        &self,
+        block_store: Arc<BlockStore>,
+        target: H256,
+    ) -> impl Future<Item = Option<String>, Error = Error> {
+        let from = self.from;
+        let justified_checkpoint = self.finality.justified_checkpoint(block_store.chain);
+        let finalised_checkpoint = self.finality.finalised_checkpoint(block_store.chain);
+        let auxiliary = Arc::clone(&self.auxiliary);
+
+        let target_height = block_height(&block_store.block_chain, target);
+
+        epochs(Arc::clone(&block_store), from)
+            .join(target_height)
+            .and_then(move |((starting_height, epoch_length), target_height)| {
+                if !is_checkpoint(target_height, starting_height, epoch_length) {
+                    return Either::A(future::ok(None));
+                }
+                if let Some(finalised_checkpoint) = finalised_checkpoint {
+                    if target_height <= finalised_checkpoint.height {
+                        info!(
+                            "Not voting for {} checkpoint {:x}, it is not above the finalised \
+                             checkpoint {:x}",
+                            block_store.chain, target, finalised_checkpoint.hash
+                        );
+                        block_store.pending_votes.lock().unwrap().remove(&target);
+                        return Either::A(future::ok(None));
+                    }
+                }
+
+                info!(
+                    "Block {:x} at height {} is a checkpoint of {}",
+                    target, target_height, block_store.chain
+                );
+                Either::B(
+                    build_vote(
+                        Arc::clone(&block_store),
+                        justified_checkpoint,
+                        target,
+                        target_height,
+                        from,
+                    ).and_then(move |vote| cast_vote(block_store, vote, from, auxiliary)),
+                )
+            })
+    }
+}
+
+impl React for Voter {
+    /// Votes for all checkpoints that were reported in the block. Also checks the pending votes
+    /// of every block store that blocks were reported on again, as the reports may have completed
+    /// the ancestries of their targets.
+    ///
+    /// # Arguments
+    ///
+    /// * `_block` - The observed block.
+    /// * `events` - The decoded events of the block.
+    fn react(&self, _block: &Block, events: &[ContractEvent]) -> Reaction {
+        let mut targets = HashSet::new();
+        for contract_event in events {
+            if let BlockStoreEvent::BlockReported { block_hash } = contract_event.event {
+                targets.insert((contract_event.contract_type, block_hash));
+            }
+        }
+
+        let reported_on: HashSet<ContractType> = targets
+            .iter()
+            .map(|&(contract_type, _)| contract_type)
+            .collect();
+        for block_store in &self.block_stores {
+            if reported_on.contains(&block_store.contract_type) {
+                for target in block_store.pending_votes.lock().unwrap().iter() {
+                    targets.insert((block_store.contract_type, *target));
+                }
+            }
+        }
+
+        let votes: Vec<_> = targets
+            .into_iter()
+            .filter_map(|(contract_type, target)| {
+                self.block_stores
+                    .iter()
+                    .find(|block_store| block_store.contract_type == contract_type)
+                    .map(|block_store| self.vote(Arc::clone(block_store), target))
+            }).collect();
+
+        Box::new(future::join_all(votes).map(|votes| {
+            let votes: Vec<String> = votes.into_iter().flatten().collect();
+            if votes.is_empty() {
+                ReactorOutcome::Skipped
+            } else {
+                ReactorOutcome::Acted(votes.join(", "))
+            }
+        }))
+    }
+
+    fn subscriptions(&self) -> Vec<EventSubscription> {
+        self.block_stores
+            .iter()
+            .filter_map(|block_store| block_store.contract.subscription("BlockReported").ok())
+            .collect()
+    }
+}
+
+/// Builds a vote for the given target. The source is the highest justified checkpoint or, if no
+/// checkpoint was justified since the node started tracking, the head of the block store.
+fn build_vote(
+    block_store: Arc<BlockStore>,
+    justified_checkpoint: Option<Checkpoint>,
+    target: H256,
+    target_height: u64,
+    from: Address,
+) -> impl Future<Item = Vote, Error = Error> {
+    let source = match justified_checkpoint {
+        Some(source) => Either::A(future::ok((source.hash, source.height))),
+        None => {
+            let block_chain = Arc::clone(&block_store.block_chain);
+            Either::B(
+                query(&block_store.contract, "getHead", (), from).and_then(move |head| {
+                    block_height(&block_chain, head).map(move |height| (head, height))
+                }),
+            )
+        }
+    };
+
+    source.and_then(move |(source, source_height)| {
+        let core_identifier = query(&block_store.contract, "getCoreIdentifier", (), from).and_then(
+            |core_identifier: Token| match core_identifier {
+                Token::FixedBytes(ref bytes) if bytes.len() == 20 => Ok(H160::from(&bytes[..])),
+                _ => Err(Error::new(
+                    ErrorKind::ContractError,
+                    format!(
+                        "Invalid core identifier of block store: {}",
+                        core_identifier
+                    ),
+                )),
+            },
+        );
+        let transition_hash = query(&block_store.contract, "transitionHashAtBlock", target, from);
+
+        core_identifier
+            .join(transition_hash)
+            .map(move |(core_identifier, transition_hash)| Vote {
+                core_identifier,
+                transition_hash,
+                source,
+                target,
+                source_height: U256::from(source_height),
+                target_height: U256::from(target_height),
+            })
+    })
+}
+
+/// Checks a vote with the block store, signs it, and sends it to the polling place.
+/// If the block store does not accept the vote, the ancestry of the target is requested to be
+/// reported once and the vote is kept pending, so that it is checked again when blocks are
+/// reported. Returns `None` if the block store does not accept the vote yet.
+fn cast_vote(
+    block_store: Arc<BlockStore>,
+    vote: Vote,
+    from: Address,
+    auxiliary: Arc<Ethereum>,
+) -> impl Future<Item = Option<String>, Error = Error> {
+    is_vote_valid(&block_store, &vote, from).and_then(move |is_valid| {
+        if !is_valid {
+            let is_new = block_store
+                .pending_votes
+                .lock()
+                .unwrap()
+                .insert(vote.target);
+            if is_new {
+                info!(
+                    "Requesting the ancestry of {} checkpoint {:x} to be reported, the vote with \
+                     source {:x} is not valid yet",
+                    block_store.chain, vote.target, vote.source
+                );
+                block_store.requests.request(vote.target);
+            } else {
+                debug!(
+                    "Not voting for {} checkpoint {:x} with source {:x} yet, the vote is still \
+                     not valid",
+                    block_store.chain, vote.target, vote.source
+                );
+            }
+            return Either::A(future::ok(None));
+        }
+
+        let target = vote.target;
+        Either::B(
+            send_vote(Arc::clone(&block_store), vote, from, auxiliary).map(move |voted| {
+                block_store.pending_votes.lock().unwrap().remove(&target);
+                voted
+            }),
+        )
+    })
+}
+
+/// Checks with the block store whether it would accept a vote.
+fn is_vote_valid(
+    block_store: &BlockStore,
+    vote: &Vote,
+    from: Address,
+) -> impl Future<Item = bool, Error = Error> {
+    query(
+        &block_store.contract,
+        "isVoteValid",
+        (vote.transition_hash, vote.source, vote.target),
+        from,
+    )
+}
+
+/// Signs a vote and sends it to the polling place.
+fn send_vote(
+    block_store: Arc<BlockStore>,
+    vote: Vote,
+    from: Address,
+    auxiliary: Arc<Ethereum>,
+) -> impl Future<Item = Option<String>, Error = Error> {
+    let polling_place = query(&block_store.contract, "pollingPlace", (), from);
+    let signature = auxiliary.sign(Bytes(vote.message_hash().to_vec()));
+
+    polling_place
+        .join(signature)
+        .and_then(move |(polling_place, signature): (Address, _)| {
+            let (v, r, s) = signature_parameters(&signature);
+            // The ABI of the `vote` function of `contracts/gateway/PollingPlace.sol` of the
+            // OpenST mosaic-contracts.
+            let polling_place_instance = auxiliary.contract_instance(
+                polling_place,
+                include_bytes!("../contract/abi/PollingPlace.json"),
+            );
+            let data = polling_place_instance.and_then(|instance| {
+                instance.encode_input(
+                    "vote",
+                    (
+                        Token::FixedBytes(vote.core_identifier.to_vec()),
+                        vote.transition_hash,
+                        vote.source,
+                        vote.target,
+                        vote.source_height,
+                        vote.target_height,
+                        v,
+                        r,
+                        s,
+                    ),
+                )
+            });
+
+            future::result(data)
+                .and_then(move |data| auxiliary.submit_transaction(polling_place, data))
+                .and_then(move |outcome| {
+                    if outcome.status == TransactionStatus::Reverted {
+                        return Err(Error::new(
+                            ErrorKind::TransactionError,
+                            format!("Vote reverted: {}", outcome),
+                        ));
+                    }
+
+                    Ok(Some(format!(
+                        "Voted for {} checkpoint {:x} with source {:x}: {}",
+                        block_store.chain, vote.target, vote.source, outcome
+                    )))
+                })
+        })
+}
+
+/// Queries a constant function of a block store.
+fn query<P, R>(
+    block_store: &ContractInstance,
+    function: &str,
+    params: P,
+    from: Address,
+) -> impl Future<Item = R, Error = Error>
+where
+    P: Tokenize,
+    R: Detokenize,
+{
+    let function = function.to_string();
+    block_store
+        .contract()
+        .query(&function, params, from, Options::default(), None)
+        .map_err(move |error| {
+            Error::new(
+                ErrorKind::ContractError,
+                format!("Error while calling '{}' on block store: {}", function, error),
+            )
+        })
+}
+
+/// Returns the starting height and the epoch length of a block store. They are read from the
+/// block store once and cached afterwards.
+fn epochs(
+    block_store: Arc<BlockStore>,
+    from: Address,
+) -> impl Future<Item = (U256, U256), Error = Error> {
+    let cached_epochs = *block_store.epochs.lock().unwrap();
+    match cached_epochs {
+        Some(epochs) => Either::A(future::ok(epochs)),
+        None => {
+            let starting_height = query(&block_store.contract, "startingHeight", (), from);
+            let epoch_length = query(&block_store.contract, "epochLength", (), from);
+
+            Either::B(starting_height.join(epoch_length).map(move |read_epochs| {
+                *block_store.epochs.lock().unwrap() = Some(read_epochs);
+                read_epochs
+            }))
+        }
+    }
+}
+
+/// Retrieves the height of a block by its hash.
+fn block_height(block_chain: &Ethereum, hash: H256) -> impl Future<Item = u64, Error = Error> {
+    block_chain
+        .block(BlockId::from(hash), &EventFilter::default())
+        .map(|block| block.number.low_u64())
+}
+
+/// Hashes the given data with keccak256.
+fn keccak256(data: &[u8]) -> H256 {
+    let mut hash = [0u8; 32];
+    Keccak::keccak256(data, &mut hash);
+
+    H256::from(hash)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethereum::signer::Signer;
+    use secp256k1::{self, Message, RecoveryId};
+
+    fn vote() -> Vote {
+        Vote {
+            core_identifier: H160::from([0x11; 20]),
+            transition_hash: H256::from([0x22; 32]),
+            source: H256::from([0x33; 32]),
+            target: H256::from([0x44; 32]),
+            source_height: U256::from(100),
+            target_height: U256::from(0x0110),
+        }
+    }
+
+    #[test]
+    fn votes_are_hashed_like_the_polling_place_hashes_them() {
+        // `abi.encode` pads `bytes20` on the right and `uint256` on the left.
+        let mut encoded = keccak256(VOTE_MESSAGE_TYPE.as_bytes()).to_vec();
+        encoded.extend_from_slice(&[0x11; 20]);
+        encoded.extend_from_slice(&[0; 12]);
+        encoded.extend_from_slice(&[0x22; 32]);
+        encoded.extend_from_slice(&[0x33; 32]);
+        encoded.extend_from_slice(&[0x44; 32]);
+        encoded.extend_from_slice(&[0; 31]);
+        encoded.push(100);
+        encoded.extend_from_slice(&[0; 30]);
+        encoded.extend_from_slice(&[0x01, 0x10]);
+
+        assert_eq!(vote().message_hash(), keccak256(&encoded));
+    }
+
+    #[test]
+    fn the_polling_place_recovers_the_validator_from_a_vote() {
+        let signer = Signer::from_secret(&[0x46; 32]).unwrap();
+        let hash = vote().message_hash();
+        let (v, r, s) = signature_parameters(&signer.sign(&Bytes(hash.to_vec())));
+
+        // The polling place calls `ecrecover(keccak256(abi.encodePacked(
+        // "\x19Ethereum Signed Message:\n32", voteHash)), v, r, s)`.
+        let mut prefixed = b"\x19Ethereum Signed Message:\n32".to_vec();
+        prefixed.extend_from_slice(&hash);
+        let mut compact = [0u8; 64];
+        compact[0..32].copy_from_slice(&r);
+        compact[32..64].copy_from_slice(&s);
+        let public_key = secp256k1::recover(
+            &Message::parse(&keccak256(&prefixed).0),
+            &secp256k1::Signature::parse(&compact),
+            &RecoveryId::parse_rpc(v.low_u32() as u8).unwrap(),
+        ).unwrap()
+        .serialize();
+
+        assert!(v == U256::from(27) || v == U256::from(28));
+        assert_eq!(
+            Address::from(&keccak256(&public_key[1..]).0[12..]),
+            signer.address()
+        );
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2018 OpenST Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module persists the state of the node between runs in a local JSON file.
+
+use serde_json;
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use web3::types::H256;
+
+use config::Chain;
+use ethereum::types::{Error, ErrorKind};
+
+/// The state of the node for all chains.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct State {
+    #[serde(default)]
+    origin: ChainState,
+    #[serde(default)]
+    auxiliary: ChainState,
+}
+
+/// The state of the node for a single chain.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ChainState {
+    /// The number of the last block that was handed to all reactors.
+    pub last_processed_block: Option<u64>,
+    /// The highest checkpoint of the chain that its block store justified.
+    #[serde(default)]
+    pub justified_checkpoint: Option<Checkpoint>,
+    /// The highest checkpoint of the chain that its block store finalised.
+    #[serde(default)]
+    pub finalised_checkpoint: Option<Checkpoint>,
+    /// The justified checkpoints that were replaced by higher ones, oldest first. They are
+    /// restored if the events of the higher ones are dropped in a reorg.
+    #[serde(default)]
+    pub previous_justified_checkpoints: Vec<Checkpoint>,
+    /// The finalised checkpoints that were replaced by higher ones, oldest first. They are
+    /// restored if the events of the higher ones are dropped in a reorg.
+    #[serde(default)]
+    pub previous_finalised_checkpoints: Vec<Checkpoint>,
+}
+
+/// A checkpoint that a block store justified or finalised.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The hash of the checkpoint.
+    pub hash: H256,
+    /// The height of the checkpoint on its chain.
+    pub height: u64,
+    /// The number of the auxiliary block whose event justified or finalised the checkpoint.
+    pub event_block: u64,
+}
+
+/// A state store reads the state from a file and writes every update back to it.
+pub struct StateStore {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl StateStore {
+    /// Opens the state store at the given path. Starts with an empty state if the file does not
+    /// exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the state file.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the file exists but cannot be read or parsed.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let state = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|error| {
+                Error::new(
+                    ErrorKind::StateError,
+                    format!("Invalid state file {}: {}", path.display(), error),
+                )
+            })?,
+            Err(ref error) if error.kind() == IoErrorKind::NotFound => {
+                info!("No state file at {}, starting fresh.", path.display());
+                State::default()
+            }
+            Err(error) => {
+                return Err(Error::new(
+                    ErrorKind::StateError,
+                    format!("Could not read state file {}: {}", path.display(), error),
+                ));
+            }
+        };
+
+        Ok(StateStore {
+            path: path.to_owned(),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Returns the current state of the given chain.
+    pub fn chain(&self, chain: Chain) -> ChainState {
+        let state = self.state.lock().unwrap();
+        match chain {
+            Chain::Origin => state.origin.clone(),
+            Chain::Auxiliary => state.auxiliary.clone(),
+        }
+    }
+
+    /// Updates the state of the given chain and writes the whole state to the state file.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The chain whose state to update.
+    /// * `update` - A function that updates the state of the chain.
+    pub fn update<F>(&self, chain: Chain, update: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut ChainState),
+    {
+        let mut state = self.state.lock().unwrap();
+        match chain {
+            Chain::Origin => update(&mut state.origin),
+            Chain::Auxiliary => update(&mut state.auxiliary),
+        };
+
+        self.write(&state)
+    }
+
+    /// Writes the state to a temporary file first and then moves it to the state file, so that
+    /// the state file is never left half written.
+    fn write(&self, state: &State) -> Result<(), Error> {
+        let content = serde_json::to_string_pretty(state).map_err(|error| {
+            Error::new(
+                ErrorKind::StateError,
+                format!("Could not serialize state: {}", error),
+            )
+        })?;
+
+        let temporary_path = self.path.with_extension("tmp");
+        fs::write(&temporary_path, content)
+            .and_then(|_| fs::rename(&temporary_path, &self.path))
+            .map_err(|error| {
+                Error::new(
+                    ErrorKind::StateError,
+                    format!(
+                        "Could not write state file {}: {}",
+                        self.path.display(),
+                        error
+                    ),
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn the_state_store_persists_the_state() {
+        let path = env::temp_dir().join(format!(
+            "mosaic_the_state_store_persists_the_state_{}.json",
+            process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let store = StateStore::open(&path).unwrap();
+        assert_eq!(store.chain(Chain::Origin).last_processed_block, None);
+        store
+            .update(Chain::Origin, |state| state.last_processed_block = Some(42))
+            .unwrap();
+
+        let store = StateStore::open(&path).unwrap();
+        assert_eq!(store.chain(Chain::Origin).last_processed_block, Some(42));
+        assert_eq!(store.chain(Chain::Auxiliary).last_processed_block, None);
+
+        fs::remove_file(&path).unwrap();
+    }
+}